@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use serde::de::{self, IntoDeserializer};
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use xml::reader::XmlEvent;
+
+use de::{visit_text, Deserializer};
+use error::{Error, Result};
+
+/// An element read ahead of time, for when serde has to see all of it before picking the type to
+/// deserialize it into, as it does for untagged enums and flattened fields.
+#[derive(Debug)]
+pub enum Content {
+    /// An attribute value, or an element with no attributes that only holds text.
+    Text(String),
+    /// Text read with `infer_scalars` set, which is reported as an integer, a float or a boolean
+    /// when it looks like one.
+    Scalar(String),
+    /// Repeated child elements with the same name.
+    Seq(Vec<Content>),
+    /// The attributes, children and text of an element, keyed like `MapAccess` does.
+    Map(Vec<(String, Content)>),
+}
+
+impl Content {
+    /// Reads the next element, start and end tags included.
+    pub fn read<R: Read>(de: &mut Deserializer<R>) -> Result<Content> {
+        let (name, attributes) = expect!(
+            de.next()?,
+            XmlEvent::StartElement { name, attributes, .. } => Ok((name, attributes))
+        )?;
+        let infer_scalars = de.infer_scalars;
+        let has_attributes = !attributes.is_empty();
+        let mut entries: Vec<(String, Content)> = attributes
+            .into_iter()
+            .map(|attr| (attr.name.local_name, Content::text(attr.value, infer_scalars)))
+            .collect();
+        loop {
+            let key = match *de.peek()? {
+                XmlEvent::StartElement { ref name, .. } => name.local_name.clone(),
+                XmlEvent::Characters(_) => "$value".to_string(),
+                _ => break,
+            };
+            let value = if key == "$value" {
+                expect!(
+                    de.next()?,
+                    XmlEvent::Characters(s) => Ok(Content::text(s, infer_scalars))
+                )?
+            } else {
+                Content::read(de)?
+            };
+            entries.push((key, value));
+        }
+        de.expect_end_element(name)?;
+
+        if entries.is_empty() {
+            return Ok(Content::text(String::new(), infer_scalars));
+        }
+        if !has_attributes && entries.len() == 1 && entries[0].0 == "$value" {
+            return Ok(entries.pop().unwrap().1);
+        }
+        Ok(Content::Map(group(entries)))
+    }
+
+    fn text(text: String, infer_scalars: bool) -> Content {
+        if infer_scalars {
+            Content::Scalar(text)
+        } else {
+            Content::Text(text)
+        }
+    }
+}
+
+/// Collects the values of repeated keys into a `Content::Seq`, keeping the keys in the order they
+/// first appeared in.
+fn group(entries: Vec<(String, Content)>) -> Vec<(String, Content)> {
+    let mut grouped: Vec<(String, Content)> = Vec::with_capacity(entries.len());
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for (key, value) in entries {
+        if let Some(&i) = positions.get(&key) {
+            let previous = &mut grouped[i].1;
+            match *previous {
+                Content::Seq(ref mut items) => items.push(value),
+                _ => {
+                    let first = ::std::mem::replace(previous, Content::Seq(Vec::new()));
+                    *previous = Content::Seq(vec![first, value]);
+                },
+            }
+            continue;
+        }
+        positions.insert(key.clone(), grouped.len());
+        grouped.push((key, value));
+    }
+    grouped
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Content {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_type_content {
+    ($deserialize:ident => $visit:ident) => {
+        fn $deserialize<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            match self {
+                Content::Text(text) | Content::Scalar(text) => visitor.$visit(text.parse()?),
+                content => content.deserialize_any(visitor),
+            }
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Content {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            Content::Text(text) => visitor.visit_string(text),
+            Content::Scalar(text) => visit_text(visitor, text),
+            Content::Seq(items) => visitor.visit_seq(SeqDeserializer::new(items.into_iter())),
+            Content::Map(entries) => visitor.visit_map(MapDeserializer::new(entries.into_iter())),
+        }
+    }
+
+    deserialize_type_content!(deserialize_i8 => visit_i8);
+    deserialize_type_content!(deserialize_i16 => visit_i16);
+    deserialize_type_content!(deserialize_i32 => visit_i32);
+    deserialize_type_content!(deserialize_i64 => visit_i64);
+    deserialize_type_content!(deserialize_u8 => visit_u8);
+    deserialize_type_content!(deserialize_u16 => visit_u16);
+    deserialize_type_content!(deserialize_u32 => visit_u32);
+    deserialize_type_content!(deserialize_u64 => visit_u64);
+    deserialize_type_content!(deserialize_f32 => visit_f32);
+    deserialize_type_content!(deserialize_f64 => visit_f64);
+    deserialize_type_content!(deserialize_bool => visit_bool);
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self {
+            Content::Text(text) | Content::Scalar(text) => visitor.visit_string(text),
+            content => content.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct enum
+        ignored_any
+    }
+}
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use error::{ErrorKind, Result};
+
+/// How entities declared in the document type definition of a document are handled.
+///
+/// The five predefined entities such as `&amp;` and character references such as `&#233;` are
+/// always expanded. External entities, declared with `SYSTEM` or `PUBLIC`, are never fetched and
+/// documents declaring them are always rejected.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde;
+/// # extern crate serde_xml_rs;
+/// # use serde::Deserialize;
+/// # use serde_xml_rs::Deserializer;
+/// # use serde_xml_rs::de::EntityPolicy;
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// struct Note {
+///     author: String,
+/// }
+/// # fn main() {
+/// let s = r##"
+///     <!DOCTYPE note [ <!ENTITY me "Jane Doe"> ]>
+///     <note><author>&me;</author></note>
+/// "##;
+///
+/// let mut de = Deserializer::new_from_reader(s.as_bytes());
+/// assert!(Note::deserialize(&mut de).is_err());
+///
+/// let mut de = Deserializer::new_from_reader(s.as_bytes())
+///     .entity_policy(EntityPolicy::Internal { max_expansion_length: 1024, max_expansion_depth: 2 });
+/// let note = Note::deserialize(&mut de).unwrap();
+/// assert_eq!(note, Note { author: "Jane Doe".to_string() });
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EntityPolicy {
+    /// Rejects any document that has a document type definition.
+    RejectDtd,
+    /// Accepts a document type definition as long as it doesn't declare any entities. This is the
+    /// default.
+    #[default]
+    PredefinedOnly,
+    /// Expands the internal entities declared in the document type definition, failing with
+    /// `ErrorKind::LimitExceeded` once an expansion gets longer than `max_expansion_length`
+    /// characters or nests more than `max_expansion_depth` entities.
+    ///
+    /// The declarations are measured against the budget as soon as the root element starts,
+    /// before any of them is expanded in the content. Deserializers created with
+    /// `Deserializer::new_from_reader` also hand the budget to the parser, which covers
+    /// entities used in the attributes of the root element itself.
+    Internal {
+        max_expansion_length: usize,
+        max_expansion_depth: u8,
+    },
+}
+
+impl EntityPolicy {
+    /// Checks the document type definition of a document against this policy, including the
+    /// expansion budget of `Internal`.
+    pub(crate) fn check(&self, doctype: &str) -> Result<()> {
+        let violation = match *self {
+            EntityPolicy::RejectDtd => Some("document type definitions are not allowed"),
+            EntityPolicy::PredefinedOnly if declarations(doctype).next().is_some() => {
+                Some("entity declarations are not allowed")
+            },
+            _ if declarations(doctype).any(is_external) => {
+                Some("external entities are not allowed")
+            },
+            _ => None,
+        };
+        if let Some(violation) = violation {
+            return Err(ErrorKind::EntityPolicy(violation.to_string()).into());
+        }
+        self.check_expansion(doctype)
+    }
+
+    /// Checks that none of the internal entities declared in the document type definition
+    /// expands beyond the budget of `Internal`.
+    fn check_expansion(&self, doctype: &str) -> Result<()> {
+        let (max_length, max_depth) = match *self {
+            EntityPolicy::Internal { max_expansion_length, max_expansion_depth } => {
+                (max_expansion_length, usize::from(max_expansion_depth))
+            },
+            _ => return Ok(()),
+        };
+        let values: HashMap<&str, &str> = declarations(doctype).filter_map(internal).collect();
+        let mut expansions = HashMap::new();
+        for name in values.keys() {
+            let (length, depth) = expansion(name, &values, &mut expansions, &mut Vec::new());
+            if length > max_length {
+                return Err(
+                    ErrorKind::LimitExceeded("entity expansion".to_string(), max_length as u64)
+                        .into(),
+                );
+            }
+            if depth > max_depth {
+                return Err(
+                    ErrorKind::LimitExceeded("entity expansion depth".to_string(), max_depth as u64)
+                        .into(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The name and replacement text of an internal general entity declaration.
+fn internal(declaration: &str) -> Option<(&str, &str)> {
+    let declaration = declaration.trim_start();
+    if declaration.starts_with('%') {
+        return None;
+    }
+    let name_end = declaration.find(char::is_whitespace)?;
+    let rest = declaration[name_end..].trim_start();
+    let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let value = &rest[1..];
+    Some((&declaration[..name_end], &value[..value.find(quote)?]))
+}
+
+/// How many characters an entity expands to, and how deeply entities nest in its expansion.
+/// Entities that refer back to themselves expand without bounds.
+fn expansion<'a>(
+    name: &'a str,
+    values: &HashMap<&'a str, &'a str>,
+    expansions: &mut HashMap<&'a str, (usize, usize)>,
+    expanding: &mut Vec<&'a str>,
+) -> (usize, usize) {
+    if let Some(&known) = expansions.get(name) {
+        return known;
+    }
+    if expanding.contains(&name) {
+        return (usize::MAX, usize::MAX);
+    }
+    let value = values[name];
+    expanding.push(name);
+    let (mut length, mut depth) = (0usize, 0);
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        length = length.saturating_add(rest[..start].chars().count());
+        rest = &rest[start + 1..];
+        let end = rest.find(';').unwrap_or(rest.len());
+        match values.get_key_value(&rest[..end]) {
+            Some((&reference, _)) => {
+                let (inner_length, inner_depth) = expansion(reference, values, expansions, expanding);
+                length = length.saturating_add(inner_length);
+                depth = depth.max(inner_depth);
+            },
+            // A predefined entity or a character reference.
+            None => length = length.saturating_add(1),
+        }
+        rest = &rest[(end + 1).min(rest.len())..];
+    }
+    length = length.saturating_add(rest.chars().count());
+    expanding.pop();
+    let known = (length, depth.saturating_add(1));
+    expansions.insert(name, known);
+    known
+}
+
+/// The entity declarations of a document type definition, each starting right after `<!ENTITY`.
+fn declarations(doctype: &str) -> impl Iterator<Item = &str> {
+    doctype.split("<!ENTITY").skip(1)
+}
+
+fn is_external(declaration: &str) -> bool {
+    let mut words = declaration.split_whitespace();
+    let name = match words.next() {
+        Some("%") => words.next(),
+        name => name,
+    };
+    name.is_some() && match words.next() {
+        Some(word) => word.starts_with("SYSTEM") || word.starts_with("PUBLIC"),
+        None => false,
+    }
+}
@@ -0,0 +1,47 @@
+/// Upper bounds on what a document may contain, for reading input that can't be trusted.
+///
+/// Every limit is off by default. Once a document goes over one of them, deserialization stops
+/// with `ErrorKind::LimitExceeded`.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde;
+/// # extern crate serde_xml_rs;
+/// # use serde::Deserialize;
+/// # use serde_xml_rs::{Deserializer, ErrorKind};
+/// # use serde_xml_rs::de::Limits;
+/// #[derive(Debug, Deserialize)]
+/// struct Item {
+///     name: String,
+/// }
+/// # fn main() {
+/// let s = r##"<item><name>a very long name</name></item>"##;
+/// let limits = Limits {
+///     max_text_length: Some(8),
+///     ..Limits::default()
+/// };
+/// let mut de = Deserializer::new_from_reader(s.as_bytes()).limits(limits);
+/// match Item::deserialize(&mut de) {
+///     Err(e) => match *e.kind() {
+///         ErrorKind::LimitExceeded(..) => {},
+///         _ => panic!("unexpected error: {}", e),
+///     },
+///     Ok(item) => panic!("unexpected item: {:?}", item),
+/// }
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// How deeply elements may be nested, the root element being at depth 1.
+    pub max_depth: Option<usize>,
+    /// How many attributes a single element may have.
+    pub max_attributes: Option<usize>,
+    /// How many bytes a single text node or attribute value may hold.
+    pub max_text_length: Option<usize>,
+    /// How many events, such as start tags, end tags and text nodes, the document may consist of.
+    pub max_events: Option<u64>,
+    /// How many bytes may be read from the source. This only applies to deserializers created
+    /// with `Deserializer::new_from_reader`.
+    pub max_input_size: Option<u64>,
+}
@@ -1,28 +1,65 @@
 use std::io::Read;
 
 use serde::de::{self, IntoDeserializer};
+use serde::de::value::SeqDeserializer;
 use xml::attribute::OwnedAttribute;
+use xml::name::OwnedName;
 use xml::reader::XmlEvent;
 
 use Deserializer;
-use error::{Error, Result};
+use de::visit_text;
+use error::{Error, ErrorKind, Result};
 
 pub struct MapAccess<'a, R: 'a + Read> {
+    /// The name of the element, given to the `$name` field before anything else.
+    name: Option<String>,
     attrs: ::std::vec::IntoIter<OwnedAttribute>,
     next_value: Option<String>,
     de: &'a mut Deserializer<R>,
+    /// The fields of the struct being read, if it has a `$value` field which gets the content
+    /// that doesn't belong to any of the others.
+    fields: &'static [&'static str],
     inner_value: bool,
+    reading_value: bool,
+    /// How deeply the element being read is nested.
+    depth: usize,
+    /// The `$comments` and `$processing_instructions` fields still to be given, after the
+    /// attributes and children.
+    note_fields: Vec<&'static str>,
+    next_notes: Option<Vec<String>>,
 }
 
 impl<'a, R: 'a + Read> MapAccess<'a, R> {
-    pub fn new(de: &'a mut Deserializer<R>, attrs: Vec<OwnedAttribute>, inner_value: bool) -> Self {
+    pub fn new(
+        de: &'a mut Deserializer<R>,
+        attrs: Vec<OwnedAttribute>,
+        fields: &'static [&'static str],
+    ) -> Self {
+        let note_fields = ["$processing_instructions", "$comments"]
+            .iter()
+            .cloned()
+            .filter(|field| fields.contains(field))
+            .collect();
+        de.value_fields = &[];
         MapAccess {
+            name: None,
             attrs: attrs.into_iter(),
             next_value: None,
-            de: de,
-            inner_value: inner_value,
+            depth: de.depth,
+            de,
+            fields,
+            inner_value: fields.contains(&"$value"),
+            reading_value: false,
+            note_fields,
+            next_notes: None,
         }
     }
+
+    /// Passes the name of the element as the first entry, under the key `$name`.
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
 }
 
 impl<'de, 'a, R: 'a + Read> de::MapAccess<'de> for MapAccess<'a, R> {
@@ -30,36 +67,65 @@ impl<'de, 'a, R: 'a + Read> de::MapAccess<'de> for MapAccess<'a, R> {
 
     fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
         debug_assert_eq!(self.next_value, None);
+        if let Some(name) = self.name.take() {
+            self.next_value = Some(name);
+            return seed.deserialize("$name".into_deserializer()).map(Some);
+        }
         match self.attrs.next() {
             Some(OwnedAttribute { name, value }) => {
                 self.next_value = Some(value);
-                seed.deserialize(name.local_name.into_deserializer())
-                    .map(Some)
+                seed.deserialize(KeyDeserializer(name.local_name)).map(Some)
             },
             None => match *self.de.peek()? {
-                XmlEvent::StartElement { ref name, .. } => seed.deserialize(
-                    if !self.inner_value {
-                        name.local_name.as_str()
+                XmlEvent::StartElement { ref name, .. } => {
+                    // With a `$value` field, the elements that aren't named after any other
+                    // field go to it.
+                    self.reading_value =
+                        self.inner_value && !self.fields.contains(&name.local_name.as_str());
+                    let key = if !self.reading_value {
+                        name.local_name.clone()
                     } else {
-                        "$value"
-                    }.into_deserializer(),
-                ).map(Some),
-                XmlEvent::Characters(_) => seed.deserialize("$value".into_deserializer()).map(Some),
-                _ => Ok(None),
+                        "$value".to_string()
+                    };
+                    seed.deserialize(KeyDeserializer(key)).map(Some)
+                },
+                XmlEvent::Characters(_) => {
+                    self.reading_value = true;
+                    seed.deserialize("$value".into_deserializer()).map(Some)
+                },
+                _ => match self.note_fields.pop() {
+                    Some(field) => {
+                        let comments = field == "$comments";
+                        self.next_notes = Some(self.de.take_notes(self.depth, comments));
+                        seed.deserialize(field.into_deserializer()).map(Some)
+                    },
+                    None => Ok(None),
+                },
             },
         }
     }
 
     fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        if let Some(notes) = self.next_notes.take() {
+            return seed.deserialize(SeqDeserializer::new(notes.into_iter()));
+        }
         match self.next_value.take() {
-            Some(value) => seed.deserialize(AttrValueDeserializer(value)),
+            Some(value) => seed.deserialize(AttrValueDeserializer(value, self.de.infer_scalars)),
+            None if self.reading_value => {
+                self.de.value_fields = self.fields;
+                let result = seed.deserialize(&mut *self.de);
+                self.de.value_fields = &[];
+                self.de.drop_notes(self.depth);
+                result
+            },
             None => {
-                if !self.inner_value {
-                    if let XmlEvent::StartElement { .. } = *self.de.peek()? {
-                        self.de.set_map_value();
-                    }
+                if let XmlEvent::StartElement { .. } = *self.de.peek()? {
+                    self.de.set_map_value();
                 }
+                self.de.presence_field = !self.fields.is_empty();
                 let result = seed.deserialize(&mut *self.de)?;
+                self.de.presence_field = false;
+                self.de.drop_notes(self.depth);
                 Ok(result)
             },
         }
@@ -70,7 +136,60 @@ impl<'de, 'a, R: 'a + Read> de::MapAccess<'de> for MapAccess<'a, R> {
     }
 }
 
-struct AttrValueDeserializer(String);
+/// Reads a map from repeated elements with the same name, keyed by one of their attributes.
+pub struct KeyedMapAccess<'a, R: 'a + Read> {
+    de: &'a mut Deserializer<R>,
+    name: String,
+    attribute: OwnedName,
+    next_key: Option<String>,
+}
+
+impl<'a, R: 'a + Read> KeyedMapAccess<'a, R> {
+    /// Starts at the next element, whose key was already taken from its attributes.
+    pub fn new(de: &'a mut Deserializer<R>, attribute: OwnedName, key: String) -> Result<Self> {
+        let name = expect!(de.peek()?, XmlEvent::StartElement { name, .. } => {
+            Ok(name.local_name.clone())
+        })?;
+        Ok(KeyedMapAccess {
+            de,
+            name,
+            attribute,
+            next_key: Some(key),
+        })
+    }
+}
+
+impl<'de, 'a, R: 'a + Read> de::MapAccess<'de> for KeyedMapAccess<'a, R> {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        let key = match self.next_key.take() {
+            Some(key) => key,
+            None => {
+                match *self.de.peek()? {
+                    XmlEvent::StartElement { ref name, .. } if name.local_name == self.name => {},
+                    _ => return Ok(None),
+                }
+                match self.de.take_attribute(&self.attribute)? {
+                    Some(key) => key,
+                    None => {
+                        let message = format!("missing the `{}` attribute", self.attribute);
+                        return Err(ErrorKind::Custom(message).into());
+                    },
+                }
+            },
+        };
+        seed.deserialize(KeyDeserializer(key)).map(Some)
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        self.de.set_map_value();
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+/// The value of an attribute, and whether `infer_scalars` is set.
+struct AttrValueDeserializer(String, bool);
 
 macro_rules! deserialize_type_attr {
     ($deserialize:ident => $visit:ident) => {
@@ -84,6 +203,14 @@ impl<'de> de::Deserializer<'de> for AttrValueDeserializer {
     type Error = Error;
 
     fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.1 {
+            visit_text(visitor, self.0)
+        } else {
+            visitor.visit_string(self.0)
+        }
+    }
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
         visitor.visit_string(self.0)
     }
 
@@ -111,12 +238,97 @@ impl<'de> de::Deserializer<'de> for AttrValueDeserializer {
         visitor.visit_some(self)
     }
 
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
     fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
         visitor.visit_bool(!self.0.is_empty())
     }
 
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        unit seq map unit_struct tuple_struct struct tuple ignored_any
+    }
+}
+
+/// Reads the key of a map entry from the name of an element or attribute, or from the value of
+/// the attribute that keys the map. Besides strings, keys can be numbers, booleans and unit
+/// variants of enums.
+struct KeyDeserializer(String);
+
+impl<'de> de::Deserializer<'de> for KeyDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.0)
+    }
+
+    deserialize_type_attr!(deserialize_i8 => visit_i8);
+    deserialize_type_attr!(deserialize_i16 => visit_i16);
+    deserialize_type_attr!(deserialize_i32 => visit_i32);
+    deserialize_type_attr!(deserialize_i64 => visit_i64);
+    deserialize_type_attr!(deserialize_u8 => visit_u8);
+    deserialize_type_attr!(deserialize_u16 => visit_u16);
+    deserialize_type_attr!(deserialize_u32 => visit_u32);
+    deserialize_type_attr!(deserialize_u64 => visit_u64);
+    deserialize_type_attr!(deserialize_f32 => visit_f32);
+    deserialize_type_attr!(deserialize_f64 => visit_f64);
+
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0.as_str() {
+            "true" | "1" => visitor.visit_bool(true),
+            "false" | "0" => visitor.visit_bool(false),
+            _ => Err(de::Error::invalid_value(de::Unexpected::Str(&self.0), &"a boolean")),
+        }
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
     forward_to_deserialize_any! {
-        char str string unit seq bytes map unit_struct newtype_struct tuple_struct
-        struct identifier tuple ignored_any byte_buf
+        char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
     }
 }
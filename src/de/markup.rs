@@ -0,0 +1,101 @@
+use std::io::Read;
+
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
+use xml::name::OwnedName;
+use xml::namespace::Namespace;
+use xml::reader::XmlEvent;
+
+use error::{ErrorKind, Result};
+use value::namespace_declarations;
+use super::Deserializer;
+
+impl<R: Read> Deserializer<R> {
+    /// Reads the next element with everything in it, and writes it back out as markup. With
+    /// `inner` set, only the content of the element is written. Without `wrapped` as well, the
+    /// content is the rest of the element being read, up to its end tag, which is left for the
+    /// caller.
+    ///
+    /// The markup is equivalent to what was read rather than a copy of it: character references
+    /// are expanded, and the elements in it declare every namespace they use, including the ones
+    /// that were declared further up in the document.
+    pub(super) fn read_markup(&mut self, inner: bool, wrapped: bool) -> Result<String> {
+        let rest = inner && !wrapped;
+        let mut markup = String::new();
+        let mut scopes = Vec::new();
+        self.capturing = true;
+        let result = loop {
+            if rest && scopes.is_empty() {
+                match self.peek() {
+                    Ok(&XmlEvent::EndElement { .. }) | Ok(&XmlEvent::EndDocument) => break Ok(markup),
+                    Ok(_) => {},
+                    Err(e) => break Err(e),
+                }
+            }
+            let event = match self.next() {
+                Ok(event) => event,
+                Err(e) => break Err(e),
+            };
+            match event {
+                XmlEvent::StartElement { .. } if inner && wrapped && scopes.is_empty() => {
+                    scopes.push(Namespace::empty());
+                },
+                XmlEvent::StartElement { name, attributes, namespace } => {
+                    markup.push('<');
+                    push_name(&mut markup, &name);
+                    for (name, uri) in namespace_declarations(&namespace, scopes.last()) {
+                        markup.push_str(&format!(" {}=\"{}\"", name, escape_str_attribute(&uri)));
+                    }
+                    for attr in attributes {
+                        markup.push(' ');
+                        push_name(&mut markup, &attr.name);
+                        markup.push_str(&format!("=\"{}\"", escape_str_attribute(&attr.value)));
+                    }
+                    markup.push('>');
+                    scopes.push(namespace);
+                },
+                XmlEvent::EndElement { .. } if inner && wrapped && scopes.len() == 1 => {
+                    break Ok(markup);
+                },
+                XmlEvent::EndElement { name } => {
+                    markup.push_str("</");
+                    push_name(&mut markup, &name);
+                    markup.push('>');
+                    scopes.pop();
+                    if scopes.is_empty() && !rest {
+                        break Ok(markup);
+                    }
+                },
+                XmlEvent::Characters(text) | XmlEvent::Whitespace(text) => {
+                    markup.push_str(&escape_str_pcdata(&text));
+                },
+                XmlEvent::CData(text) => {
+                    markup.push_str(&format!("<![CDATA[{}]]>", text));
+                },
+                XmlEvent::Comment(text) => {
+                    markup.push_str(&format!("<!--{}-->", text));
+                },
+                XmlEvent::ProcessingInstruction { name, data } => {
+                    markup.push_str(&format!("<?{}", name));
+                    if let Some(data) = data {
+                        markup.push_str(&format!(" {}", data));
+                    }
+                    markup.push_str("?>");
+                },
+                event => {
+                    let found = format!("{:?}", event);
+                    break Err(ErrorKind::UnexpectedToken("StartElement".to_string(), found).into());
+                },
+            }
+        };
+        self.capturing = false;
+        result
+    }
+}
+
+fn push_name(markup: &mut String, name: &OwnedName) {
+    if let Some(ref prefix) = name.prefix {
+        markup.push_str(prefix);
+        markup.push(':');
+    }
+    markup.push_str(&name.local_name);
+}
@@ -1,20 +1,38 @@
+use std::collections::VecDeque;
 use std::io::Read;
 
 use serde::de;
 use xml::reader::{EventReader, ParserConfig, XmlEvent};
 use xml::name::OwnedName;
+use xml::namespace::Namespace;
 
+use encoding::Encoding;
 use error::{Error, ErrorKind, Result};
-use self::map::MapAccess;
+use self::buffer::Content;
+use self::map::{KeyedMapAccess, MapAccess};
+use self::reader::Reader;
 use self::seq::SeqAccess;
 use self::var::EnumAccess;
+use {MARKUP_NEWTYPE, RAW_MARKUP_NEWTYPE, XSI_NAMESPACE};
 
+pub use self::entities::EntityPolicy;
+pub use self::limits::Limits;
+
+mod buffer;
+mod entities;
+mod html5;
+mod limits;
 mod map;
+mod markup;
+mod reader;
 mod seq;
 mod var;
 
 /// A convenience method for deserialize some object from a string.
 ///
+/// Number and `bool` fields of `#[serde(flatten)]` structs and of tagged or untagged enums can't
+/// be read this way, as their text stays a string; see `Deserializer::infer_scalars`.
+///
 /// ```rust
 /// # #[macro_use]
 /// # extern crate serde_derive;
@@ -57,64 +75,644 @@ pub fn from_str<'de, T: de::Deserialize<'de>>(s: &str) -> Result<T> {
 /// # }
 /// ```
 pub fn from_reader<'de, R: Read, T: de::Deserialize<'de>>(reader: R) -> Result<T> {
-    T::deserialize(&mut Deserializer::new_from_reader(reader))
+    let mut de = Deserializer::new_from_reader(reader);
+    let value = T::deserialize(&mut de)?;
+    de.end()?;
+    Ok(value)
 }
 
 pub struct Deserializer<R: Read> {
     depth: usize,
-    reader: EventReader<R>,
-    peeked: Option<XmlEvent>,
+    reader: Reader<R>,
+    peeked: VecDeque<XmlEvent>,
     is_map_value: bool,
+    /// The fields of the struct whose `$value` is read next. Their elements end the sequence
+    /// that `$value` may hold, and markup it holds is the rest of the content of the struct
+    /// rather than an element of its own. Cleared by whatever reads the value.
+    value_fields: &'static [&'static str],
+    variant_attribute: Option<OwnedName>,
+    map_key_attribute: Option<OwnedName>,
+    empty_as_none: bool,
+    bool_as_presence: bool,
+    /// Set while the value of a struct field is read, until it turns out not to be a `bool` of
+    /// its own, as only such a `bool` is read from the presence of the element.
+    presence_field: bool,
+    infer_scalars: bool,
+    limits: Limits,
+    entity_policy: EntityPolicy,
+    encoding: Option<Encoding>,
+    fragment: bool,
+    strict_root: bool,
+    capture_comments: bool,
+    /// The comments and processing instructions that were read while `capture_comments` is set,
+    /// along with how deeply nested they are.
+    notes: Vec<(usize, XmlEvent)>,
+    /// Set while an element is captured as markup, which needs every event as it was read.
+    capturing: bool,
+    /// How many events were read so far, including the peeked ones.
+    events: u64,
+    /// How deeply nested the last event that was read is, including the peeked ones.
+    read_depth: usize,
+    /// An option that was set but can't be applied to the `EventReader` that was passed in,
+    /// reported as soon as something is read.
+    unsupported: Option<&'static str>,
 }
 
-impl<'de, R: Read> Deserializer<R> {
+impl<R: Read> Deserializer<R> {
     pub fn new(reader: EventReader<R>) -> Self {
+        Self::with_reader(Reader::Events(reader))
+    }
+
+    pub fn new_from_reader(reader: R) -> Self {
+        let config = ParserConfig::new()
+            .trim_whitespace(false)
+            .whitespace_to_characters(false)
+            .cdata_to_characters(false)
+            .ignore_comments(false)
+            .coalesce_characters(true);
+
+        Self::with_reader(Reader::Pending(Some(reader), config))
+    }
+
+    fn with_reader(reader: Reader<R>) -> Self {
         Deserializer {
             depth: 0,
-            reader: reader,
-            peeked: None,
+            reader,
+            peeked: VecDeque::new(),
             is_map_value: false,
+            value_fields: &[],
+            variant_attribute: None,
+            map_key_attribute: None,
+            empty_as_none: false,
+            bool_as_presence: false,
+            presence_field: false,
+            infer_scalars: false,
+            limits: Limits::default(),
+            entity_policy: EntityPolicy::default(),
+            encoding: None,
+            fragment: false,
+            strict_root: false,
+            capture_comments: false,
+            notes: Vec::new(),
+            capturing: false,
+            events: 0,
+            read_depth: 0,
+            unsupported: None,
         }
     }
 
-    pub fn new_from_reader(reader: R) -> Self {
-        let config = ParserConfig::new()
-            .trim_whitespace(true)
-            .whitespace_to_characters(true)
-            .cdata_to_characters(true)
-            .ignore_comments(true)
-            .coalesce_characters(true);
+    /// Selects enum variants by the value of the given attribute, such as `type` or `xsi:type`,
+    /// when the element holding the enum has that attribute. The remaining attributes and
+    /// children of the element become the content of the variant. Elements without the attribute
+    /// are still matched by name as usual.
+    ///
+    /// A prefix is resolved against the namespaces in scope of the element, and `xsi` always
+    /// stands for the XML Schema instance namespace.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::Deserializer;
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// enum Shape {
+    ///     #[serde(rename = "circle")]
+    ///     Circle { r: f64 },
+    ///     #[serde(rename = "square")]
+    ///     Square { side: f64 },
+    /// }
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Drawing {
+    ///     shape: Shape,
+    /// }
+    /// # fn main() {
+    /// let s = r##"<drawing><shape type="circle" r="3" /></drawing>"##;
+    /// let mut de = Deserializer::new_from_reader(s.as_bytes()).variant_attribute("type");
+    /// let drawing = Drawing::deserialize(&mut de).unwrap();
+    /// assert_eq!(drawing, Drawing { shape: Shape::Circle { r: 3.0 } });
+    /// # }
+    /// ```
+    pub fn variant_attribute(mut self, name: &str) -> Self {
+        self.variant_attribute = Some(parse_name(name));
+        self
+    }
+
+    /// Reads a map field from repeated elements named after the field, keyed by the value of
+    /// the given attribute, when the first of these elements has that attribute. The content of
+    /// each element is the value for its key. Other maps are still keyed by element names.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use std::collections::HashMap;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::Deserializer;
+    /// #[derive(Debug, Deserialize)]
+    /// struct Config {
+    ///     name: String,
+    ///     setting: HashMap<String, u32>,
+    /// }
+    /// # fn main() {
+    /// let s = r##"
+    ///     <config>
+    ///         <name>server</name>
+    ///         <setting key="timeout">30</setting>
+    ///         <setting key="retries">5</setting>
+    ///     </config>
+    /// "##;
+    /// let mut de = Deserializer::new_from_reader(s.as_bytes()).map_key_attribute("key");
+    /// let config = Config::deserialize(&mut de).unwrap();
+    /// assert_eq!(config.setting["timeout"], 30);
+    /// assert_eq!(config.setting["retries"], 5);
+    /// # }
+    /// ```
+    pub fn map_key_attribute(mut self, name: &str) -> Self {
+        self.map_key_attribute = Some(parse_name(name));
+        self
+    }
+
+    /// Reads empty elements, such as `<field/>` or `<field></field>`, as `None` when they stand
+    /// for an `Option`. By default they are read as `Some` of an empty value, which suits
+    /// `Option<String>`. Elements marked with `xsi:nil="true"` are always read as `None`.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::Deserializer;
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Person {
+    ///     name: String,
+    ///     nickname: Option<String>,
+    /// }
+    /// # fn main() {
+    /// let s = r##"<person><name>Joe</name><nickname /></person>"##;
+    /// let mut de = Deserializer::new_from_reader(s.as_bytes()).empty_as_none(true);
+    /// let person = Person::deserialize(&mut de).unwrap();
+    /// assert_eq!(person, Person { name: "Joe".to_string(), nickname: None });
+    /// # }
+    /// ```
+    pub fn empty_as_none(mut self, set: bool) -> Self {
+        self.empty_as_none = set;
+        self
+    }
+
+    /// Reads empty elements, such as `<required/>`, as `true` when they stand for a `bool` held
+    /// by a struct field, rather than by an `Option` or a sequence. Elements with text are still
+    /// read as `true` or `false`. A field that may be left out for `false` needs
+    /// `#[serde(default)]`.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::Deserializer;
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Param {
+    ///     name: String,
+    ///     #[serde(default)]
+    ///     required: bool,
+    ///     #[serde(default)]
+    ///     deprecated: bool,
+    /// }
+    /// # fn main() {
+    /// let s = r##"<param><name>id</name><required/></param>"##;
+    /// let mut de = Deserializer::new_from_reader(s.as_bytes()).bool_as_presence(true);
+    /// let param = Param::deserialize(&mut de).unwrap();
+    /// assert_eq!(param, Param { name: "id".to_string(), required: true, deprecated: false });
+    /// # }
+    /// ```
+    pub fn bool_as_presence(mut self, set: bool) -> Self {
+        self.bool_as_presence = set;
+        self
+    }
+
+    /// Reports text that looks like an integer, a float or a boolean as such to types that
+    /// accept anything. Serde buffers the content of `#[serde(flatten)]` fields and internally
+    /// tagged enums this way, and can't turn buffered text into a number by itself, so this is
+    /// needed for their number and `bool` fields, which fail with an `invalid type: string`
+    /// error otherwise. A `String` field among them then can't hold text such as `42`; leading
+    /// zeros such as in `007` keep the text a string.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::Deserializer;
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Stats {
+    ///     count: u32,
+    ///     enabled: bool,
+    /// }
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Report {
+    ///     name: String,
+    ///     #[serde(flatten)]
+    ///     stats: Stats,
+    /// }
+    /// # fn main() {
+    /// let s = r##"<report name="daily" count="3" enabled="true" />"##;
+    /// let mut de = Deserializer::new_from_reader(s.as_bytes()).infer_scalars(true);
+    /// let report = Report::deserialize(&mut de).unwrap();
+    /// assert_eq!(report.stats, Stats { count: 3, enabled: true });
+    /// # }
+    /// ```
+    pub fn infer_scalars(mut self, set: bool) -> Self {
+        self.infer_scalars = set;
+        self
+    }
+
+    /// Bounds what the document may contain, see `Limits`.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets how entities declared in the document type definition are handled, see
+    /// `EntityPolicy`.
+    pub fn entity_policy(mut self, policy: EntityPolicy) -> Self {
+        self.entity_policy = policy;
+        self
+    }
 
-        Self::new(EventReader::new_with_config(reader, config))
+    /// Reads the document in the given encoding, whatever its byte order mark or XML declaration
+    /// say. A byte order mark of the given encoding is still skipped.
+    ///
+    /// Without this, the encoding is taken from the byte order mark, or else from the XML
+    /// declaration, and defaults to UTF-8. This only applies to deserializers created with
+    /// `Deserializer::new_from_reader`.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::{Deserializer, Encoding};
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Payment {
+    ///     amount: String,
+    /// }
+    /// # fn main() {
+    /// // Declared as ISO-8859-1, but really Windows-1252.
+    /// let s = b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><payment><amount>\x8050</amount></payment>";
+    /// let mut de = Deserializer::new_from_reader(&s[..]).encoding(Encoding::Windows1252);
+    /// let payment = Payment::deserialize(&mut de).unwrap();
+    /// assert_eq!(payment, Payment { amount: "\u{20ac}50".to_string() });
+    /// # }
+    /// ```
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Expands the given named entities, such as `("nbsp", "\u{a0}")`, without the document
+    /// having to declare them. The replacement text is taken as is, it is never parsed as markup.
+    ///
+    /// This only applies to deserializers created with `Deserializer::new_from_reader`, an
+    /// `EventReader` has to be set up with its own entities. A deserializer created with
+    /// `Deserializer::new` fails with `ErrorKind::UnsupportedOperation` instead of reading
+    /// anything.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use std::collections::HashMap;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::Deserializer;
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Footer {
+    ///     copyright: String,
+    /// }
+    /// # fn main() {
+    /// let s = r##"<footer><copyright>&copy; ACME&trade;</copyright></footer>"##;
+    /// let mut entities = HashMap::new();
+    /// entities.insert("copy", "\u{a9}");
+    /// entities.insert("trade", "\u{2122}");
+    /// let mut de = Deserializer::new_from_reader(s.as_bytes()).entities(entities);
+    /// let footer = Footer::deserialize(&mut de).unwrap();
+    /// assert_eq!(footer, Footer { copyright: "\u{a9} ACME\u{2122}".to_string() });
+    /// # }
+    /// ```
+    pub fn entities<I, K, V>(mut self, entities: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        match self.reader.config_mut() {
+            Some(config) => for (name, value) in entities {
+                config.extra_entities.insert(name.into(), value.into());
+            },
+            None => {
+                warn!("Entities can't be added to an EventReader that was passed in");
+                self.unsupported = Some("entities");
+            },
+        }
+        self
+    }
+
+    /// Expands the named character references of HTML5, such as `&nbsp;` or `&eacute;`, see
+    /// `entities`.
+    pub fn html5_entities(self) -> Self {
+        self.entities(html5::ENTITIES.iter().cloned())
+    }
+
+    /// Reads a fragment made of any number of elements one after the other, instead of a
+    /// document with a single root element. A sequence then gets an item for each element.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::Deserializer;
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// struct Entry {
+    ///     level: String,
+    /// }
+    /// # fn main() {
+    /// let s = r##"<entry level="info"/><entry level="warn"/>"##;
+    /// let mut de = Deserializer::new_from_reader(s.as_bytes()).fragment(true);
+    /// let entries = Vec::<Entry>::deserialize(&mut de).unwrap();
+    /// de.end().unwrap();
+    /// assert_eq!(entries.len(), 2);
+    /// # }
+    /// ```
+    ///
+    /// Without this, a second root element is a syntax error. This only applies to
+    /// deserializers created with `Deserializer::new_from_reader`.
+    pub fn fragment(mut self, set: bool) -> Self {
+        self.fragment = set;
+        self
+    }
+
+    /// Requires the root element to be named like the struct it is read into, after any
+    /// `#[serde(rename)]`. The name may carry a prefix, as in `inv:Invoice`, or the struct may
+    /// be renamed to the prefixed name.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::{Deserializer, ErrorKind};
+    /// #[derive(Debug, Deserialize)]
+    /// struct Order {
+    ///     id: u32,
+    /// }
+    /// # fn main() {
+    /// let s = r##"<Invoice id="7"/>"##;
+    /// let mut de = Deserializer::new_from_reader(s.as_bytes()).strict_root(true);
+    /// let error = Order::deserialize(&mut de).unwrap_err();
+    /// assert_eq!(error.to_string(), "expected the root element Order, found Invoice");
+    /// # }
+    /// ```
+    pub fn strict_root(mut self, set: bool) -> Self {
+        self.strict_root = set;
+        self
+    }
+
+    /// Keeps the comments and processing instructions of the document, for structs to receive
+    /// in a `$comments` or `$processing_instructions` field. Such a field, usually a
+    /// `Vec<String>`, gets the ones found directly in the element of the struct, and for the root
+    /// element also the ones in front of it. A processing instruction comes as its target
+    /// followed by its data, such as `app-version 3.2`. Without this, these fields are left
+    /// empty.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Deserialize;
+    /// # use serde_xml_rs::Deserializer;
+    /// #[derive(Debug, Deserialize)]
+    /// struct Config {
+    ///     #[serde(rename = "$comments")]
+    ///     comments: Vec<String>,
+    ///     #[serde(rename = "$processing_instructions")]
+    ///     processing_instructions: Vec<String>,
+    ///     name: String,
+    /// }
+    /// # fn main() {
+    /// let s = r##"<!-- Licensed under MIT --><?app-version 3.2?><config><name>main</name></config>"##;
+    /// let mut de = Deserializer::new_from_reader(s.as_bytes()).capture_comments(true);
+    /// let config = Config::deserialize(&mut de).unwrap();
+    /// assert_eq!(config.comments, vec![" Licensed under MIT "]);
+    /// assert_eq!(config.processing_instructions, vec!["app-version 3.2"]);
+    /// # }
+    /// ```
+    ///
+    /// The `Element` tree always keeps them.
+    pub fn capture_comments(mut self, set: bool) -> Self {
+        self.capture_comments = set;
+        self
+    }
+
+    /// Checks that nothing but whitespace, comments and processing instructions follow what was
+    /// deserialized, up to the end of the document. `from_str` and `from_reader` do this after
+    /// deserializing their value.
+    pub fn end(&mut self) -> Result<()> {
+        expect!(self.next()?, XmlEvent::EndDocument => Ok(()))
     }
 
     fn peek(&mut self) -> Result<&XmlEvent> {
-        if self.peeked.is_none() {
-            self.peeked = Some(self.inner_next()?);
+        self.peek_nth(0)
+    }
+
+    /// Looks `n` events past the next one without consuming anything.
+    fn peek_nth(&mut self, n: usize) -> Result<&XmlEvent> {
+        while self.peeked.len() <= n {
+            self.read_event()?;
         }
-        debug_expect!(self.peeked.as_ref(), Some(peeked) => {
+        debug_expect!(self.peeked.get(n), Some(peeked) => {
             debug!("Peeked {:?}", peeked);
             Ok(peeked)
         })
     }
 
     fn inner_next(&mut self) -> Result<XmlEvent> {
+        if let Some(option) = self.unsupported {
+            return Err(ErrorKind::UnsupportedOperation(option.to_string()).into());
+        }
         loop {
-            match self.reader.next().map_err(ErrorKind::Syntax)? {
-                XmlEvent::StartDocument { .. } |
-                XmlEvent::ProcessingInstruction { .. } |
-                XmlEvent::Comment(_) => { /* skip */ },
+            let event = self.reader.next(
+                &self.limits,
+                &self.entity_policy,
+                self.encoding,
+                self.fragment,
+            )?;
+            self.check_limits(&event)?;
+            if let XmlEvent::StartElement { .. } = event {
+                if self.read_depth == 1 {
+                    if let Some(doctype) = self.reader.doctype() {
+                        // The parser doesn't notice an internal subset that isn't closed.
+                        if !internal_subset_closed(doctype) {
+                            return Err(self.reader.syntax_error("unclosed internal subset"));
+                        }
+                        self.entity_policy.check(doctype)?;
+                    }
+                }
+            }
+            match event {
+                XmlEvent::StartDocument { .. } => { /* skip */ },
                 other => return Ok(other),
             }
         }
     }
 
+    /// Reads the next event into `peeked`. Comments and processing instructions are skipped, and
+    /// text is joined with any CDATA sections next to it and trimmed, unless an element is being
+    /// captured.
+    fn read_event(&mut self) -> Result<()> {
+        loop {
+            let event = self.inner_next()?;
+            if self.capturing {
+                self.peeked.push_back(event);
+                return Ok(());
+            }
+            match event {
+                XmlEvent::ProcessingInstruction { .. } | XmlEvent::Comment(_) => self.note(event),
+                first @ XmlEvent::Characters(_) |
+                first @ XmlEvent::Whitespace(_) |
+                first @ XmlEvent::CData(_) => {
+                    // The text is trimmed, except for what is inside CDATA sections.
+                    let mut text = String::new();
+                    let mut cdata: Option<(usize, usize)> = None;
+                    // The length of the text so far, for `max_text_length`, which leaves out
+                    // whitespace between elements.
+                    let mut length = 0;
+                    let mut event = first;
+                    loop {
+                        match event {
+                            XmlEvent::Whitespace(more) => text.push_str(&more),
+                            XmlEvent::Characters(more) => {
+                                text.push_str(&more);
+                                length += more.len();
+                                check("text length", length, self.limits.max_text_length)?;
+                            },
+                            XmlEvent::CData(more) => {
+                                let start = cdata.map_or(text.len(), |(start, _)| start);
+                                text.push_str(&more);
+                                cdata = Some((start, text.len()));
+                                length += more.len();
+                                check("text length", length, self.limits.max_text_length)?;
+                            },
+                            note @ XmlEvent::ProcessingInstruction { .. } |
+                            note @ XmlEvent::Comment(_) => self.note(note),
+                            other => {
+                                let (keep_start, keep_end) = cdata.unwrap_or((text.len(), 0));
+                                let start = text[..keep_start].len() -
+                                    text[..keep_start].trim_start().len();
+                                let end = keep_end.max(start) +
+                                    text[keep_end.max(start)..].trim_end().len();
+                                if start < end {
+                                    text.truncate(end);
+                                    text.drain(..start);
+                                    self.peeked.push_back(XmlEvent::Characters(text));
+                                }
+                                self.peeked.push_back(other);
+                                return Ok(());
+                            },
+                        }
+                        event = self.inner_next()?;
+                    }
+                },
+                other => {
+                    self.peeked.push_back(other);
+                    return Ok(());
+                },
+            }
+        }
+    }
+
+    /// Keeps a comment or processing instruction that was left out of the events, if
+    /// `capture_comments` is set.
+    fn note(&mut self, event: XmlEvent) {
+        if self.capture_comments {
+            self.notes.push((self.read_depth, event));
+        }
+    }
+
+    /// Takes the comments, or else the processing instructions, found directly in an element
+    /// at the given depth, or in front of it if it is the root.
+    fn take_notes(&mut self, depth: usize, comments: bool) -> Vec<String> {
+        let mut taken = Vec::new();
+        let mut kept = Vec::new();
+        for (note_depth, event) in self.notes.drain(..) {
+            let belongs = note_depth == depth || (depth == 1 && note_depth == 0);
+            match event {
+                XmlEvent::Comment(text) if belongs && comments => taken.push(text),
+                XmlEvent::ProcessingInstruction { name, data } if belongs && !comments => {
+                    taken.push(match data {
+                        Some(data) => format!("{} {}", name, data),
+                        None => name,
+                    });
+                },
+                event => kept.push((note_depth, event)),
+            }
+        }
+        self.notes = kept;
+        taken
+    }
+
+    /// Drops the notes nested deeper than the given depth, once the elements holding them were
+    /// read without taking them.
+    fn drop_notes(&mut self, depth: usize) {
+        self.notes.retain(|&(note_depth, _)| note_depth <= depth);
+    }
+
+    fn check_limits(&mut self, event: &XmlEvent) -> Result<()> {
+        let limits = self.limits;
+        self.events += 1;
+        if limits.max_events.is_some_and(|max| self.events > max) {
+            let max = limits.max_events.unwrap_or_default();
+            return Err(ErrorKind::LimitExceeded("number of events".to_string(), max).into());
+        }
+        match *event {
+            XmlEvent::StartElement { ref attributes, .. } => {
+                self.read_depth += 1;
+                check("nesting depth", self.read_depth, limits.max_depth)?;
+                check("number of attributes", attributes.len(), limits.max_attributes)?;
+                for attr in attributes {
+                    check("attribute length", attr.value.len(), limits.max_text_length)?;
+                }
+            },
+            XmlEvent::EndElement { .. } => {
+                self.read_depth -= 1;
+            },
+            XmlEvent::Characters(ref text) |
+            XmlEvent::CData(ref text) |
+            XmlEvent::Comment(ref text) => {
+                check("text length", text.len(), limits.max_text_length)?;
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
     fn next(&mut self) -> Result<XmlEvent> {
-        let next = if let Some(peeked) = self.peeked.take() {
-            peeked
-        } else {
-            self.inner_next()?
-        };
+        if self.peeked.is_empty() {
+            self.read_event()?;
+        }
+        let next = debug_expect!(self.peeked.pop_front(), Some(next) => next);
         match next {
             XmlEvent::StartElement { .. } => {
                 self.depth += 1;
@@ -136,7 +734,7 @@ impl<'de, R: Read> Deserializer<R> {
         ::std::mem::replace(&mut self.is_map_value, false)
     }
 
-    fn read_inner_value<V: de::Visitor<'de>, T, F: FnOnce(&mut Self) -> Result<T>>(
+    fn read_inner_value<T, F: FnOnce(&mut Self) -> Result<T>>(
         &mut self,
         f: F,
     ) -> Result<T> {
@@ -165,11 +763,57 @@ impl<'de, R: Read> Deserializer<R> {
         })
     }
 
-    fn prepare_parse_type<V: de::Visitor<'de>>(&mut self) -> Result<String> {
+    /// Checks whether the next element stands for `None`, either because it is marked with
+    /// `xsi:nil="true"` or because it is empty and `empty_as_none` is set.
+    fn peek_nil_element(&mut self) -> Result<bool> {
+        let has_attributes = match *self.peek()? {
+            XmlEvent::StartElement { ref attributes, .. } => {
+                let nil = attributes.iter().any(|attr| {
+                    attr.name.local_name == "nil" &&
+                        attr.name.namespace.as_deref() == Some(XSI_NAMESPACE) &&
+                        (attr.value == "true" || attr.value == "1")
+                });
+                if nil {
+                    return Ok(true);
+                }
+                !attributes.is_empty()
+            },
+            _ => return Ok(false),
+        };
+        if !self.empty_as_none || has_attributes {
+            return Ok(false);
+        }
+        Ok(matches!(*self.peek_nth(1)?, XmlEvent::EndElement { .. }))
+    }
+
+    /// Removes the attribute configured with `variant_attribute` from the next start tag and
+    /// returns its value, which names the variant of the enum being read.
+    fn take_variant_attribute(&mut self) -> Result<Option<String>> {
+        match self.variant_attribute.clone() {
+            Some(wanted) => self.take_attribute(&wanted),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes an attribute from the next start tag and returns its value.
+    fn take_attribute(&mut self, wanted: &OwnedName) -> Result<Option<String>> {
+        self.peek()?;
+        match self.peeked.front_mut() {
+            Some(&mut XmlEvent::StartElement { ref mut attributes, ref namespace, .. }) => {
+                let position = attributes
+                    .iter()
+                    .position(|attr| is_same_attribute(&attr.name, wanted, namespace));
+                Ok(position.map(|i| attributes.remove(i).value))
+            },
+            _ => Ok(None),
+        }
+    }
+
+    fn prepare_parse_type(&mut self) -> Result<String> {
         if let XmlEvent::StartElement { .. } = *self.peek()? {
             self.set_map_value()
         }
-        self.read_inner_value::<V, String, _>(|this| {
+        self.read_inner_value(|this| {
             if let XmlEvent::EndElement { .. } = *this.peek()? {
                 return Err(
                     ErrorKind::UnexpectedToken("EndElement".into(), "Characters".into()).into(),
@@ -177,41 +821,149 @@ impl<'de, R: Read> Deserializer<R> {
             }
 
             expect!(this.next()?, XmlEvent::Characters(s) => {
-                return Ok(s)
+                Ok(s)
             })
         })
     }
 }
 
+fn parse_name(name: &str) -> OwnedName {
+    match name.find(':') {
+        Some(i) => OwnedName {
+            local_name: name[i + 1..].to_string(),
+            namespace: None,
+            prefix: Some(name[..i].to_string()),
+        },
+        None => OwnedName::local(name),
+    }
+}
+
+/// Compares an attribute name against one given by the user, resolving the prefix of the latter
+/// in the namespace scope of the element.
+fn is_same_attribute(name: &OwnedName, wanted: &OwnedName, scope: &Namespace) -> bool {
+    if name.local_name != wanted.local_name {
+        return false;
+    }
+    match wanted.prefix {
+        Some(ref prefix) => {
+            let uri = scope.get(prefix).or_else(|| if prefix == "xsi" {
+                Some(XSI_NAMESPACE)
+            } else {
+                None
+            });
+            uri.is_some() && name.namespace.as_deref() == uri
+        },
+        None => name.namespace.is_none(),
+    }
+}
+
+/// Fails with `ErrorKind::LimitExceeded` if `value` is over the limit, if there is one.
+fn check(limit: &str, value: usize, max: Option<usize>) -> Result<()> {
+    match max {
+        Some(max) if value > max => {
+            Err(ErrorKind::LimitExceeded(limit.to_string(), max as u64).into())
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Whether the internal subset of a document type definition, if it has one, is closed by `]`.
+/// Brackets in quoted literals, such as `SYSTEM "x[1].dtd"`, don't count.
+fn internal_subset_closed(doctype: &str) -> bool {
+    let mut quote = None;
+    let mut open = false;
+    for c in doctype.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '[' => open = true,
+                ']' => open = false,
+                _ => {},
+            },
+        }
+    }
+    !open
+}
+
+/// Visits text whose type is not known in advance, such as when serde buffers content for
+/// `#[serde(flatten)]` or tagged enums, with `infer_scalars` set. Integers, floats and booleans
+/// are reported as such so that the buffered value can later be read back as a number or a `bool`.
+fn visit_text<'de, V: de::Visitor<'de>>(visitor: V, text: String) -> Result<V::Value> {
+    if let Ok(v) = text.parse::<u64>() {
+        if v.to_string() == text {
+            return visitor.visit_u64(v);
+        }
+    }
+    if let Ok(v) = text.parse::<i64>() {
+        if v.to_string() == text {
+            return visitor.visit_i64(v);
+        }
+    }
+    let fractional = text.bytes().any(|b| b.is_ascii_digit()) &&
+        text.bytes().any(|b| matches!(b, b'.' | b'e' | b'E')) &&
+        text.bytes().all(|b| matches!(b, b'0'..=b'9' | b'.' | b'e' | b'E' | b'+' | b'-'));
+    if fractional {
+        if let Ok(v) = text.parse::<f64>() {
+            return visitor.visit_f64(v);
+        }
+    }
+    match text.as_str() {
+        "true" => visitor.visit_bool(true),
+        "false" => visitor.visit_bool(false),
+        _ => visitor.visit_string(text),
+    }
+}
+
 macro_rules! deserialize_type {
     ($deserialize:ident => $visit:ident) => {
         fn $deserialize<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-            let value = self.prepare_parse_type::<V>()?.parse()?;
+            let value = self.prepare_parse_type()?.parse()?;
             visitor.$visit(value)
         }
     }
 }
 
-impl<'de, 'a, R: Read> de::Deserializer<'de> for &'a mut Deserializer<R> {
+impl<'de, R: Read> de::Deserializer<'de> for &mut Deserializer<R> {
     type Error = Error;
 
-    forward_to_deserialize_any! {
-        newtype_struct identifier
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        if name == MARKUP_NEWTYPE || name == RAW_MARKUP_NEWTYPE {
+            let map_value = self.unset_map_value();
+            let struct_value = !::std::mem::take(&mut self.value_fields).is_empty();
+            let inner = name == RAW_MARKUP_NEWTYPE;
+            return visitor.visit_string(self.read_markup(inner, map_value || !struct_value)?);
+        }
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_struct<V: de::Visitor<'de>>(
         self,
-        _name: &'static str,
+        struct_name: &'static str,
         fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value> {
         self.unset_map_value();
+        let root = self.depth == 0;
         expect!(self.next()?, XmlEvent::StartElement { name, attributes, .. } => {
-            let map_value = visitor.visit_map(MapAccess::new(
-                self,
-                attributes,
-                fields.contains(&"$value")
-            ))?;
+            let qualified = match name.prefix {
+                Some(ref prefix) => format!("{}:{}", prefix, name.local_name),
+                None => name.local_name.clone(),
+            };
+            let expected = name.local_name == struct_name || qualified == struct_name;
+            if root && self.strict_root && !expected {
+                return Err(ErrorKind::UnexpectedRoot(struct_name.to_string(), qualified).into());
+            }
+            let mut map = MapAccess::new(self, attributes, fields);
+            if fields.contains(&"$name") {
+                map = map.with_name(qualified);
+            }
+            let map_value = visitor.visit_map(map)?;
             self.expect_end_element(name)?;
             Ok(map_value)
         })
@@ -227,7 +979,19 @@ impl<'de, 'a, R: Read> de::Deserializer<'de> for &'a mut Deserializer<R> {
     deserialize_type!(deserialize_u64 => visit_u64);
     deserialize_type!(deserialize_f32 => visit_f32);
     deserialize_type!(deserialize_f64 => visit_f64);
-    deserialize_type!(deserialize_bool => visit_bool);
+
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.bool_as_presence && ::std::mem::replace(&mut self.presence_field, false) {
+            if let XmlEvent::StartElement { .. } = *self.peek()? {
+                if let XmlEvent::EndElement { .. } = *self.peek_nth(1)? {
+                    self.deserialize_ignored_any(de::IgnoredAny)?;
+                    return visitor.visit_bool(true);
+                }
+            }
+        }
+        let value = self.prepare_parse_type()?.parse()?;
+        visitor.visit_bool(value)
+    }
 
     fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
         self.deserialize_string(visitor)
@@ -249,7 +1013,7 @@ impl<'de, 'a, R: Read> de::Deserializer<'de> for &'a mut Deserializer<R> {
         if let XmlEvent::StartElement { .. } = *self.peek()? {
             self.set_map_value()
         }
-        self.read_inner_value::<V, V::Value, _>(
+        self.read_inner_value(
             |this| expect!(this.peek()?, &XmlEvent::EndElement { .. } => visitor.visit_unit()),
         )
     }
@@ -278,17 +1042,24 @@ impl<'de, 'a, R: Read> de::Deserializer<'de> for &'a mut Deserializer<R> {
     fn deserialize_enum<V: de::Visitor<'de>>(
         self,
         _name: &'static str,
-        _variants: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value> {
-        self.read_inner_value::<V, V::Value, _>(|this| visitor.visit_enum(EnumAccess::new(this)))
+        self.value_fields = &[];
+        if let XmlEvent::StartElement { .. } = *self.peek()? {
+            if let Some(variant) = self.take_variant_attribute()? {
+                self.unset_map_value();
+                return visitor.visit_enum(EnumAccess::by_attribute(self, variant));
+            }
+        }
+        self.read_inner_value(|this| visitor.visit_enum(EnumAccess::new(this, variants)))
     }
 
     fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
         if let XmlEvent::StartElement { .. } = *self.peek()? {
             self.set_map_value()
         }
-        self.read_inner_value::<V, V::Value, _>(|this| {
+        self.read_inner_value(|this| {
             if let XmlEvent::EndElement { .. } = *this.peek()? {
                 return visitor.visit_str("");
             }
@@ -299,19 +1070,34 @@ impl<'de, 'a, R: Read> de::Deserializer<'de> for &'a mut Deserializer<R> {
     }
 
     fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.presence_field = false;
         visitor.visit_seq(SeqAccess::new(self, None))
     }
 
     fn deserialize_map<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if let Some(attribute) = self.map_key_attribute.clone() {
+            if self.is_map_value {
+                if let Some(key) = self.take_attribute(&attribute)? {
+                    self.unset_map_value();
+                    return visitor.visit_map(KeyedMapAccess::new(self, attribute, key)?);
+                }
+            }
+        }
         self.unset_map_value();
         expect!(self.next()?, XmlEvent::StartElement { name, attributes, .. } => {
-            let map_value = visitor.visit_map(MapAccess::new(self, attributes, false))?;
+            let map_value = visitor.visit_map(MapAccess::new(self, attributes, &[]))?;
             self.expect_end_element(name)?;
             Ok(map_value)
         })
     }
 
     fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        // `Some(false)` has to be told apart from `None`.
+        self.presence_field = false;
+        if self.peek_nil_element()? {
+            self.deserialize_ignored_any(de::IgnoredAny)?;
+            return visitor.visit_none();
+        }
         match *self.peek()? {
             XmlEvent::EndElement { .. } => visitor.visit_none(),
             _ => visitor.visit_some(self),
@@ -330,11 +1116,25 @@ impl<'de, 'a, R: Read> de::Deserializer<'de> for &'a mut Deserializer<R> {
         visitor.visit_unit()
     }
 
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
     fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.unset_map_value();
         match *self.peek()? {
-            XmlEvent::StartElement { .. } => self.deserialize_map(visitor),
-            XmlEvent::EndElement { .. } => self.deserialize_unit(visitor),
-            _ => self.deserialize_string(visitor),
+            XmlEvent::StartElement { .. } => {
+                de::Deserializer::deserialize_any(Content::read(self)?, visitor)
+            },
+            XmlEvent::EndElement { .. } => visitor.visit_unit(),
+            _ => {
+                let infer_scalars = self.infer_scalars;
+                expect!(self.next()?, XmlEvent::Characters(s) => if infer_scalars {
+                    visit_text(visitor, s)
+                } else {
+                    visitor.visit_string(s)
+                })
+            },
         }
     }
 }
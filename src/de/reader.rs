@@ -0,0 +1,229 @@
+use std::io::{self, Read};
+use std::mem;
+
+use xml::reader::{self, EventReader, ParserConfig, ParserConfig2, XmlEvent};
+
+use encoding::{self, Encoding};
+use error::{Error, ErrorKind, Result};
+use super::{EntityPolicy, Limits};
+
+/// How many bytes are read from the source at once, and at most looked at to work out its
+/// encoding.
+const CHUNK_SIZE: usize = 4096;
+
+/// Where a `Deserializer` gets its events from.
+pub enum Reader<R: Read> {
+    /// An `EventReader` that was configured by the user.
+    Events(EventReader<R>),
+    /// A source whose `EventReader` is only set up on the first read, so that the parser can
+    /// still be configured until then.
+    Pending(Option<R>, ParserConfig),
+    Started(EventReader<Input<R>>),
+}
+
+impl<R: Read> Reader<R> {
+    /// The parser configuration, as long as the reader is still waiting to be set up.
+    pub fn config_mut(&mut self) -> Option<&mut ParserConfig> {
+        match *self {
+            Reader::Pending(_, ref mut config) => Some(config),
+            _ => None,
+        }
+    }
+
+    pub fn next(
+        &mut self,
+        limits: &Limits,
+        entity_policy: &EntityPolicy,
+        encoding: Option<Encoding>,
+        fragment: bool,
+    ) -> Result<XmlEvent> {
+        let started = match *self {
+            Reader::Pending(ref mut source, ref config) => {
+                let inner = match source.take() {
+                    Some(source) => source,
+                    None => unreachable!(),
+                };
+                let input = Input {
+                    inner,
+                    read: 0,
+                    max: limits.max_input_size,
+                    encoding,
+                    sniffed: false,
+                    raw: Vec::new(),
+                    decoded: Vec::new(),
+                    pos: 0,
+                    failure: None,
+                };
+                let (length, depth) = match *entity_policy {
+                    EntityPolicy::Internal { max_expansion_length, max_expansion_depth } => {
+                        (max_expansion_length, max_expansion_depth)
+                    },
+                    _ => (0, 0),
+                };
+                // The input is turned into UTF-8 before it gets to the parser, whatever the
+                // declaration says.
+                let config = ParserConfig2::from(config.clone())
+                    .override_encoding(Some(::xml::Encoding::Utf8))
+                    .ignore_invalid_encoding_declarations(true)
+                    .allow_multiple_root_elements(fragment)
+                    .max_entity_expansion_length(length)
+                    .max_entity_expansion_depth(depth);
+                Some(EventReader::new_with_config(input, config))
+            },
+            _ => None,
+        };
+        if let Some(reader) = started {
+            *self = Reader::Started(reader);
+        }
+
+        match *self {
+            Reader::Events(ref mut reader) => Ok(reader.next().map_err(ErrorKind::Syntax)?),
+            Reader::Started(ref mut reader) => match reader.next() {
+                Ok(event) => Ok(event),
+                Err(e) => match reader.source_mut().failure.take() {
+                    Some(failure) => Err(failure.into()),
+                    None => {
+                        // The parser stops at an entity that expands beyond its budget, or one
+                        // the policy doesn't allow, with a syntax error of its own. In the
+                        // attributes of the root element that comes before the policy got to
+                        // see the document type definition.
+                        if let Some(doctype) = reader.doctype() {
+                            entity_policy.check(doctype)?;
+                        }
+                        Err(ErrorKind::Syntax(e).into())
+                    },
+                },
+            },
+            Reader::Pending(..) => unreachable!(),
+        }
+    }
+
+    /// The document type definition, once the reader got past it.
+    pub fn doctype(&self) -> Option<&str> {
+        match *self {
+            Reader::Events(ref reader) => reader.doctype(),
+            Reader::Started(ref reader) => reader.doctype(),
+            Reader::Pending(..) => None,
+        }
+    }
+
+    /// A syntax error at the current position of the parser.
+    pub fn syntax_error(&self, message: &'static str) -> Error {
+        let error = match *self {
+            Reader::Events(ref reader) => reader::Error::from((reader, message)),
+            Reader::Started(ref reader) => reader::Error::from((reader, message)),
+            Reader::Pending(..) => unreachable!(),
+        };
+        ErrorKind::Syntax(error).into()
+    }
+}
+
+/// Wraps the source of a document to turn it into UTF-8, and to stop reading once it gets
+/// larger than allowed.
+pub struct Input<R: Read> {
+    inner: R,
+    read: u64,
+    max: Option<u64>,
+    /// The encoding of the source, either given up front or worked out from its first bytes.
+    encoding: Option<Encoding>,
+    sniffed: bool,
+    /// Bytes from the source which weren't decoded yet.
+    raw: Vec<u8>,
+    decoded: Vec<u8>,
+    pos: usize,
+    /// Why reading failed, in more detail than the parser reports.
+    failure: Option<ErrorKind>,
+}
+
+impl<R: Read> Input<R> {
+    fn fail(&mut self, failure: ErrorKind) -> io::Error {
+        let error = io::Error::other(failure.to_string());
+        self.failure = Some(failure);
+        error
+    }
+
+    /// Reads the next chunk of the source into `raw`, returning how many bytes were read.
+    fn fill(&mut self) -> io::Result<usize> {
+        let len = self.raw.len();
+        self.raw.resize(len + CHUNK_SIZE, 0);
+        let read = self.inner.read(&mut self.raw[len..]);
+        self.raw.truncate(len + *read.as_ref().unwrap_or(&0));
+        let read = read?;
+
+        self.read += read as u64;
+        if let Some(max) = self.max {
+            if self.read > max {
+                return Err(self.fail(ErrorKind::LimitExceeded("input size".to_string(), max)));
+            }
+        }
+        Ok(read)
+    }
+
+    /// Reads enough of the source to find the byte order mark and XML declaration, then picks
+    /// the encoding.
+    fn sniff(&mut self) -> io::Result<()> {
+        while self.raw.len() < CHUNK_SIZE && !self.raw.contains(&b'>') {
+            if self.fill()? == 0 {
+                break;
+            }
+        }
+
+        let (sniffed, bom) = match encoding::sniff(&self.raw) {
+            Ok(sniffed) => sniffed,
+            Err(_) if self.encoding.is_some() => (Encoding::Utf8, 0),
+            Err(label) => return Err(self.fail(ErrorKind::UnsupportedEncoding(label))),
+        };
+        let encoding = self.encoding.unwrap_or(sniffed);
+        if self.raw.starts_with(encoding.bom()) {
+            self.raw.drain(..encoding.bom().len());
+        } else if self.encoding.is_none() {
+            self.raw.drain(..bom);
+        }
+        self.encoding = Some(encoding);
+        self.sniffed = true;
+
+        self.decode();
+        // Leave out any whitespace in front of the XML declaration, which the parser rejects.
+        let start = self.decoded.iter().position(|b| !b" \t\r\n".contains(b));
+        if let Some(start) = start {
+            if self.decoded[start..].starts_with(b"<?xml") {
+                self.pos = start;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode(&mut self) {
+        let encoding = self.encoding.unwrap_or(Encoding::Utf8);
+        let raw = mem::take(&mut self.raw);
+        self.decoded.drain(..self.pos);
+        self.pos = 0;
+        let used = encoding.decode(&raw, &mut self.decoded);
+        self.raw = raw[used..].to_vec();
+    }
+}
+
+impl<R: Read> Read for Input<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(ref failure) = self.failure {
+            return Err(io::Error::other(failure.to_string()));
+        }
+        if !self.sniffed {
+            self.sniff()?;
+        }
+        while self.pos == self.decoded.len() {
+            if self.fill()? == 0 {
+                if !self.raw.is_empty() {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete character"));
+                }
+                return Ok(0);
+            }
+            self.decode();
+        }
+
+        let len = buf.len().min(self.decoded.len() - self.pos);
+        buf[..len].copy_from_slice(&self.decoded[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
@@ -10,21 +10,26 @@ pub struct SeqAccess<'a, R: 'a + Read> {
     de: &'a mut Deserializer<R>,
     max_size: Option<usize>,
     expected_name: Option<String>,
+    /// Elements that end the sequence, because they belong to other fields of the struct whose
+    /// `$value` it is.
+    other_fields: &'static [&'static str],
 }
 
 impl<'a, R: 'a + Read> SeqAccess<'a, R> {
     pub fn new(de: &'a mut Deserializer<R>, max_size: Option<usize>) -> Self {
         let expected_name = if de.unset_map_value() {
-            debug_expect!(de.peek(), Ok(&XmlEvent::StartElement { ref name, .. }) => {
+            debug_expect!(de.peek(), Ok(XmlEvent::StartElement { name, .. }) => {
                 Some(name.local_name.clone())
             })
         } else {
             None
         };
+        let other_fields = ::std::mem::take(&mut de.value_fields);
         SeqAccess {
-            de: de,
-            max_size: max_size,
-            expected_name: expected_name,
+            de,
+            max_size,
+            expected_name,
+            other_fields,
         }
     }
 }
@@ -46,12 +51,15 @@ impl<'de, 'a, R: 'a + Read> de::SeqAccess<'de> for SeqAccess<'a, R> {
             None => {},
         }
         let more = match (self.de.peek()?, self.expected_name.as_ref()) {
-            (&XmlEvent::StartElement { ref name, .. }, Some(expected_name)) => {
+            (XmlEvent::StartElement { name, .. }, Some(expected_name)) => {
                 &name.local_name == expected_name
             },
-            (&XmlEvent::EndElement { .. }, None) |
+            (XmlEvent::StartElement { name, .. }, None) => {
+                !self.other_fields.contains(&name.local_name.as_str())
+            },
+            (XmlEvent::EndElement { .. }, None) |
             (_, Some(_)) |
-            (&XmlEvent::EndDocument { .. }, _) => false,
+            (XmlEvent::EndDocument, _) => false,
             (_, None) => true,
         };
         if more {
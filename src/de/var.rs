@@ -1,19 +1,37 @@
 use std::io::Read;
 
 use serde::de::{self, Deserializer as SerdeDeserializer, IntoDeserializer};
+use serde::de::value::StringDeserializer;
 use xml::name::OwnedName;
 use xml::reader::XmlEvent;
 
 use de::Deserializer;
 use error::{Error, Result};
+use OTHER_VARIANT;
 
 pub struct EnumAccess<'a, R: 'a + Read> {
     de: &'a mut Deserializer<R>,
+    variants: &'static [&'static str],
+    attribute_variant: Option<String>,
 }
 
 impl<'a, R: 'a + Read> EnumAccess<'a, R> {
-    pub fn new(de: &'a mut Deserializer<R>) -> Self {
-        EnumAccess { de: de }
+    pub fn new(de: &'a mut Deserializer<R>, variants: &'static [&'static str]) -> Self {
+        EnumAccess {
+            de,
+            variants,
+            attribute_variant: None,
+        }
+    }
+
+    /// Reads an enum whose variant was named by an attribute of the next element, which holds
+    /// the content of the variant.
+    pub fn by_attribute(de: &'a mut Deserializer<R>, variant: String) -> Self {
+        EnumAccess {
+            de,
+            variants: &[],
+            attribute_variant: Some(variant),
+        }
     }
 }
 
@@ -25,14 +43,32 @@ impl<'de, 'a, R: 'a + Read> de::EnumAccess<'de> for EnumAccess<'a, R> {
         self,
         seed: V,
     ) -> Result<(V::Value, VariantAccess<'a, R>)> {
+        if let Some(variant) = self.attribute_variant {
+            let deserializer: StringDeserializer<Error> = variant.into_deserializer();
+            let name = seed.deserialize(deserializer)?;
+            return Ok((name, VariantAccess::by_attribute(self.de)));
+        }
+        // An element that doesn't name a variant goes to the `$other` variant, if the enum has
+        // one.
+        let other = match *self.de.peek()? {
+            XmlEvent::StartElement { ref name, .. } => {
+                let variants = self.variants;
+                !variants.contains(&name.local_name.as_str()) && variants.contains(&OTHER_VARIANT)
+            },
+            _ => false,
+        };
         let name = expect!(
             self.de.peek()?,
 
             &XmlEvent::Characters(ref name) |
             &XmlEvent::StartElement { name: OwnedName { local_name: ref name, .. }, .. } => {
-                seed.deserialize(name.as_str().into_deserializer())
+                let name = if other { OTHER_VARIANT } else { name.as_str() };
+                seed.deserialize(name.into_deserializer())
             }
         )?;
+        if other {
+            return Ok((name, VariantAccess::other(self.de)));
+        }
         self.de.set_map_value();
         Ok((name, VariantAccess::new(self.de)))
     }
@@ -40,11 +76,35 @@ impl<'de, 'a, R: 'a + Read> de::EnumAccess<'de> for EnumAccess<'a, R> {
 
 pub struct VariantAccess<'a, R: 'a + Read> {
     de: &'a mut Deserializer<R>,
+    by_attribute: bool,
+    /// Set for the `$other` variant, which gets the whole element that didn't match any other
+    /// variant.
+    other: bool,
 }
 
 impl<'a, R: 'a + Read> VariantAccess<'a, R> {
     pub fn new(de: &'a mut Deserializer<R>) -> Self {
-        VariantAccess { de: de }
+        VariantAccess {
+            de,
+            by_attribute: false,
+            other: false,
+        }
+    }
+
+    pub fn by_attribute(de: &'a mut Deserializer<R>) -> Self {
+        VariantAccess {
+            de,
+            by_attribute: true,
+            other: false,
+        }
+    }
+
+    pub fn other(de: &'a mut Deserializer<R>) -> Self {
+        VariantAccess {
+            de,
+            by_attribute: false,
+            other: true,
+        }
     }
 }
 
@@ -52,6 +112,9 @@ impl<'de, 'a, R: 'a + Read> de::VariantAccess<'de> for VariantAccess<'a, R> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
+        if self.by_attribute || self.other {
+            return self.de.deserialize_ignored_any(de::IgnoredAny).map(|_| ());
+        }
         self.de.unset_map_value();
         match self.de.next()? {
             XmlEvent::StartElement {
@@ -67,18 +130,57 @@ impl<'de, 'a, R: 'a + Read> de::VariantAccess<'de> for VariantAccess<'a, R> {
     }
 
     fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        if self.by_attribute {
+            self.de.set_map_value();
+        }
         seed.deserialize(&mut *self.de)
     }
 
     fn tuple_variant<V: de::Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value> {
+        if self.other {
+            let name = expect!(self.de.peek()?, XmlEvent::StartElement { name, .. } => {
+                Ok(name.local_name.clone())
+            })?;
+            return visitor.visit_seq(OtherAccess { de: self.de, name: Some(name) });
+        }
+        if self.by_attribute {
+            self.de.set_map_value();
+            return self.de.read_inner_value(|this| this.deserialize_tuple(len, visitor));
+        }
         self.de.deserialize_tuple(len, visitor)
     }
 
     fn struct_variant<V: de::Visitor<'de>>(
         self,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value> {
+        if self.other {
+            return self.de.deserialize_struct(OTHER_VARIANT, fields, visitor);
+        }
         self.de.deserialize_map(visitor)
     }
 }
+
+/// Gives the `$other` variant the name of the element, followed by the element itself.
+struct OtherAccess<'a, R: 'a + Read> {
+    de: &'a mut Deserializer<R>,
+    name: Option<String>,
+}
+
+impl<'de, 'a, R: 'a + Read> de::SeqAccess<'de> for OtherAccess<'a, R> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>> {
+        match self.name.take() {
+            Some(name) => {
+                let deserializer: StringDeserializer<Error> = name.into_deserializer();
+                seed.deserialize(deserializer).map(Some)
+            },
+            None => seed.deserialize(&mut *self.de).map(Some),
+        }
+    }
+}
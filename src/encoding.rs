@@ -0,0 +1,192 @@
+use std::char;
+
+/// A character encoding of XML documents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, which maps every byte to the code point of the same value.
+    Latin1,
+    /// Windows-1252, which is ISO-8859-1 with printable characters in place of most of the C1
+    /// control characters.
+    Windows1252,
+}
+
+/// The characters of Windows-1252 from 0x80 to 0x9F. The five bytes it leaves undefined are
+/// mapped to the C1 control characters, as in ISO-8859-1.
+const WINDOWS_1252: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+impl Encoding {
+    /// Looks up an encoding by a name as used in the `encoding` of an XML declaration, ignoring
+    /// case. Plain `UTF-16` is taken to be big endian, as the specification says for documents
+    /// without a byte order mark.
+    pub fn from_label(label: &str) -> Option<Encoding> {
+        match &*label.trim().to_ascii_lowercase() {
+            "utf-8" | "utf8" | "us-ascii" | "ascii" => Some(Encoding::Utf8),
+            "utf-16" | "utf16" | "utf-16be" => Some(Encoding::Utf16Be),
+            "utf-16le" => Some(Encoding::Utf16Le),
+            "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "l1" => Some(Encoding::Latin1),
+            "windows-1252" | "cp1252" | "x-cp1252" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// The name of this encoding for the `encoding` of an XML declaration.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le | Encoding::Utf16Be => "UTF-16",
+            Encoding::Latin1 => "ISO-8859-1",
+            Encoding::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// The byte order mark of this encoding.
+    pub fn bom(&self) -> &'static [u8] {
+        match *self {
+            Encoding::Utf8 => b"\xef\xbb\xbf",
+            Encoding::Utf16Le => b"\xff\xfe",
+            Encoding::Utf16Be => b"\xfe\xff",
+            Encoding::Latin1 | Encoding::Windows1252 => b"",
+        }
+    }
+
+    /// Decodes as much of `bytes` as possible into `out`, returning how many bytes were used.
+    /// Only UTF-16 can leave bytes behind, when `bytes` ends in the middle of a character.
+    pub(crate) fn decode(&self, bytes: &[u8], out: &mut Vec<u8>) -> usize {
+        match *self {
+            Encoding::Utf8 => {
+                out.extend_from_slice(bytes);
+                bytes.len()
+            },
+            Encoding::Latin1 => {
+                let mut buf = [0; 4];
+                for &b in bytes {
+                    out.extend_from_slice(char::from(b).encode_utf8(&mut buf).as_bytes());
+                }
+                bytes.len()
+            },
+            Encoding::Windows1252 => {
+                let mut buf = [0; 4];
+                for &b in bytes {
+                    let c = match b {
+                        0x80..=0x9f => WINDOWS_1252[(b - 0x80) as usize],
+                        _ => char::from(b),
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+                bytes.len()
+            },
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let unit = |pair: &[u8]| match *self {
+                    Encoding::Utf16Le => u16::from(pair[0]) | u16::from(pair[1]) << 8,
+                    _ => u16::from(pair[0]) << 8 | u16::from(pair[1]),
+                };
+                let mut buf = [0; 4];
+                let mut used = 0;
+                while bytes.len() - used >= 2 {
+                    let first = unit(&bytes[used..]);
+                    let (c, len) = match first {
+                        0xd800..=0xdbff if bytes.len() - used < 4 => break,
+                        0xd800..=0xdbff => {
+                            let second = unit(&bytes[used + 2..]);
+                            let c = char::decode_utf16(vec![first, second]).next();
+                            match c {
+                                Some(Ok(c)) => (c, 4),
+                                _ => (char::REPLACEMENT_CHARACTER, 2),
+                            }
+                        },
+                        _ => (char::from_u32(u32::from(first)).unwrap_or(char::REPLACEMENT_CHARACTER), 2),
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    used += len;
+                }
+                used
+            },
+        }
+    }
+
+    /// Encodes `c` into `out`, or returns `false` if this encoding can't represent it.
+    pub(crate) fn encode_char(&self, c: char, out: &mut Vec<u8>) -> bool {
+        let mut units = [0; 2];
+        match *self {
+            Encoding::Utf8 => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            Encoding::Utf16Le => {
+                for unit in c.encode_utf16(&mut units) {
+                    out.extend_from_slice(&unit.to_le_bytes());
+                }
+            },
+            Encoding::Utf16Be => {
+                for unit in c.encode_utf16(&mut units) {
+                    out.extend_from_slice(&unit.to_be_bytes());
+                }
+            },
+            Encoding::Latin1 | Encoding::Windows1252 => {
+                let byte = match (*self, c as u32) {
+                    (Encoding::Windows1252, _) if WINDOWS_1252.contains(&c) => {
+                        WINDOWS_1252.iter().position(|&d| d == c).map(|i| 0x80 + i as u8)
+                    },
+                    (Encoding::Windows1252, 0x80..=0x9f) => None,
+                    (_, b @ 0..=0xff) => Some(b as u8),
+                    _ => None,
+                };
+                match byte {
+                    Some(byte) => out.push(byte),
+                    None => return false,
+                }
+            },
+        }
+        true
+    }
+}
+
+/// Works out the encoding of a document from its first bytes: a byte order mark, the pattern
+/// of a UTF-16 declaration or the `encoding` of an XML declaration, in this order. Returns the
+/// encoding along with the length of the byte order mark, or the name of an unknown encoding.
+///
+/// `bytes` should reach past the end of the XML declaration, if there is one.
+pub(crate) fn sniff(bytes: &[u8]) -> Result<(Encoding, usize), String> {
+    for &encoding in &[Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+        if bytes.starts_with(encoding.bom()) {
+            return Ok((encoding, encoding.bom().len()));
+        }
+    }
+    if bytes.starts_with(b"<\0?\0") {
+        return Ok((Encoding::Utf16Le, 0));
+    }
+    if bytes.starts_with(b"\0<\0?") {
+        return Ok((Encoding::Utf16Be, 0));
+    }
+
+    match declared_encoding(bytes) {
+        Some(label) => match Encoding::from_label(&label) {
+            Some(Encoding::Utf16Le) | Some(Encoding::Utf16Be) => Ok((Encoding::Utf8, 0)),
+            Some(encoding) => Ok((encoding, 0)),
+            None => Err(label),
+        },
+        None => Ok((Encoding::Utf8, 0)),
+    }
+}
+
+/// The value of `encoding` in the XML declaration at the start of `bytes`, if there is one.
+fn declared_encoding(bytes: &[u8]) -> Option<String> {
+    let start = bytes.iter().position(|b| !b" \t\r\n".contains(b))?;
+    let bytes = &bytes[start..];
+    if !bytes.starts_with(b"<?xml") {
+        return None;
+    }
+    let end = bytes.windows(2).position(|w| w == b"?>")?;
+    let declaration = String::from_utf8_lossy(&bytes[..end]);
+
+    let rest = &declaration[declaration.find("encoding")? + "encoding".len()..];
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let rest = &rest[1..];
+    Some(rest[..rest.find(quote)?].to_string())
+}
@@ -1,3 +1,6 @@
+// error_chain 0.10 still generates the deprecated `description` and `cause` methods.
+#![allow(deprecated)]
+
 use std::fmt::Display;
 use serde::de::Error as DeError;
 use serde::ser::Error as SerError;
@@ -32,6 +35,42 @@ error_chain! {
             description("unsupported operation")
             display("unsupported operation: '{}'", operation)
         }
+        LimitExceeded(limit: String, max: u64) {
+            description("limit exceeded")
+            display("{} exceeds the limit of {}", limit, max)
+        }
+        UnsupportedEncoding(encoding: String) {
+            description("unsupported encoding")
+            display("unsupported encoding: '{}'", encoding)
+        }
+        UnexpectedRoot(expected: String, found: String) {
+            description("unexpected root element")
+            display("expected the root element {}, found {}", expected, found)
+        }
+        InvalidName(name: String) {
+            description("invalid name")
+            display("invalid XML name: '{}'", name)
+        }
+        NotScalar(found: String) {
+            description("expected a scalar")
+            display("expected a scalar such as a string or a number, found {}", found)
+        }
+        MissingRoot {
+            description("missing root element")
+            display("the document has no root element")
+        }
+        MultipleRoots(found: String) {
+            description("multiple root elements")
+            display("the document already has a root element, found another one: {}", found)
+        }
+        UnclosedElement(name: String) {
+            description("unclosed element")
+            display("the element {} was never ended", name)
+        }
+        EntityPolicy(violation: String) {
+            description("entity policy violated")
+            display("entity policy violated: {}", violation)
+        }
     }
 }
 
@@ -47,10 +47,31 @@ extern crate serde_derive;
 
 #[macro_use]
 mod error;
+mod encoding;
 pub mod de;
 pub mod ser;
+pub mod value;
 
+pub use encoding::Encoding;
 pub use error::{Error, ErrorKind};
 pub use xml::reader::{EventReader, ParserConfig};
 pub use ser::{to_string, to_writer, Serializer};
 pub use de::{from_reader, from_str, Deserializer};
+pub use value::{from_value, to_value, CData, Element, Node, RawXml};
+
+/// The name of the newtype structs which the `Deserializer` fills with the markup of a whole
+/// element, and whose content the `Serializer` writes out as markup.
+const MARKUP_NEWTYPE: &str = "$markup";
+
+/// The name of the newtype structs which the `Deserializer` fills with the markup of the content
+/// of an element, and whose content the `Serializer` writes out as markup inside the element.
+const RAW_MARKUP_NEWTYPE: &str = "$raw";
+
+/// The name of the newtype structs whose content the `Serializer` writes as a CDATA section.
+const CDATA_NEWTYPE: &str = "$cdata";
+
+/// The name of the enum variant which receives elements that don't match any other variant.
+const OTHER_VARIANT: &str = "$other";
+
+/// The namespace bound to the conventional `xsi` prefix.
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
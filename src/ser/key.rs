@@ -0,0 +1,180 @@
+use std::fmt::Display;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use error::{Error, ErrorKind, Result};
+
+/// Turns a scalar, such as the key of a map or the name of an element, into the text that stands
+/// for it in an element or attribute name. The text isn't escaped.
+pub fn to_text<T: ?Sized + Serialize>(value: &T) -> Result<String> {
+    value.serialize(KeySerializer)
+}
+
+/// A serializer for values that have to be written as a single piece of text, which rejects
+/// anything that would need markup of its own.
+struct KeySerializer;
+
+fn not_scalar(what: &str) -> Error {
+    ErrorKind::NotScalar(what.to_string()).into()
+}
+
+fn display<T: Display>(value: T) -> Result<String> {
+    Ok(value.to_string())
+}
+
+#[allow(unused_variables)]
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = Error;
+
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<String> {
+        display(v)
+    }
+
+    fn serialize_str(self, value: &str) -> Result<String> {
+        display(value)
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<String> {
+        Err(not_scalar("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<String> {
+        Ok(String::new())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<String> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<String> {
+        Ok(String::new())
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<String> {
+        Err(not_scalar("a unit struct"))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String> {
+        display(variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<String> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<String> {
+        Err(not_scalar("an enum variant with content"))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(not_scalar("a sequence"))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+        Err(not_scalar("a tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(not_scalar("a tuple struct"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(not_scalar("an enum variant with content"))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(not_scalar("a map"))
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
+        Err(not_scalar("a struct"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(not_scalar("an enum variant with content"))
+    }
+}
@@ -2,10 +2,17 @@ use std::io::Write;
 use std::fmt::Display;
 
 use serde::ser::{self, Impossible, Serialize};
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
 
+use encoding::Encoding;
 use error::{Error, ErrorKind, Result};
-use self::var::{Map, Struct};
+use self::output::Output;
+use self::var::{Map, OtherVariant, Seq, Struct};
+use value::cdata_section;
+use {CDATA_NEWTYPE, MARKUP_NEWTYPE, OTHER_VARIANT, RAW_MARKUP_NEWTYPE, XSI_NAMESPACE};
 
+mod key;
+mod output;
 mod var;
 
 
@@ -37,7 +44,8 @@ mod var;
 /// ```
 pub fn to_writer<W: Write, S: Serialize>(writer: W, value: &S) -> Result<()> {
     let mut ser = Serializer::new(writer);
-    value.serialize(&mut ser)
+    value.serialize(&mut ser)?;
+    ser.end()
 }
 
 
@@ -74,12 +82,94 @@ pub fn to_string<S: Serialize>(value: &S) -> Result<String> {
     Ok(string)
 }
 
+/// How the `Serializer` writes a field whose value is `None`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoneRepresentation {
+    /// Leave the element out entirely.
+    Omit,
+    /// Write an element with no content, such as `<field></field>`. This is the default.
+    EmptyElement,
+    /// Write an element marked with `xsi:nil="true"`.
+    XsiNil,
+}
+
+/// What the strings in a `$comments` or `$processing_instructions` field are written as.
+#[derive(Clone, Copy)]
+enum Note {
+    Comment,
+    ProcessingInstruction,
+}
+
+/// Whether the text is a name that can be given to an element, attribute or processing
+/// instruction.
+pub(crate) fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' || c == ':' => {},
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == ':' || c == '-' || c == '.')
+}
+
+/// Checks that the text can be written as a comment.
+pub(crate) fn check_comment(text: &str) -> Result<()> {
+    if text.contains("--") || text.ends_with('-') {
+        let message = format!("a comment can't contain `--` or end with `-`: {:?}", text);
+        return Err(ErrorKind::Custom(message).into());
+    }
+    Ok(())
+}
+
+/// Checks that the target and data can be written as a processing instruction.
+pub(crate) fn check_processing_instruction(target: &str, data: Option<&str>) -> Result<()> {
+    if !is_name(target) || target.eq_ignore_ascii_case("xml") {
+        return Err(ErrorKind::InvalidName(target.to_string()).into());
+    }
+    match data {
+        Some(data) if data.contains("?>") => {
+            let message = format!("processing instruction data can't contain `?>`: {:?}", data);
+            Err(ErrorKind::Custom(message).into())
+        },
+        _ => Ok(()),
+    }
+}
+
 /// An XML `Serializer`.
+///
+/// What it writes is well-formed: names are checked, text is escaped and there is a single root
+/// element, or else serializing fails. The one exception is the markup of a `RawXml`, which is
+/// written as it is.
 pub struct Serializer<W>
 where
     W: Write,
 {
-    writer: W,
+    writer: Output<W>,
+    /// The last start tag, held back without its closing `>` until the content of its element
+    /// follows, so that attributes can still be added to it or the element can be left out.
+    start_tag: Option<String>,
+    /// The names of the elements that were started and haven't ended yet.
+    elements: Vec<String>,
+    /// Set once the root element has been started, after which no other can follow it.
+    root: bool,
+    /// Set when the element that was started last was left out, so its end tag is skipped too.
+    skip_end_tag: bool,
+    variant_attribute: Option<String>,
+    map_key_attribute: Option<String>,
+    none_representation: NoneRepresentation,
+    bool_as_presence: bool,
+    /// Set while the value of a struct field is written, until it turns out not to be a `bool`
+    /// of its own, as only such a `bool` is written as the presence of the element.
+    presence_field: bool,
+    /// Set while the value of a variant named by `variant_attribute` is written, whose fields go
+    /// into the element that holds the enum.
+    variant_content: bool,
+    /// Set while strings are written as markup rather than as text.
+    markup: bool,
+    /// What strings are written as instead of text, while a `$comments` or
+    /// `$processing_instructions` field is written.
+    note: Option<Note>,
+    /// Set while strings are written as CDATA sections.
+    cdata: bool,
 }
 
 impl<W> Serializer<W>
@@ -87,20 +177,423 @@ where
     W: Write,
 {
     pub fn new(writer: W) -> Self {
-        Self { writer: writer }
+        Self {
+            writer: Output::new(writer),
+            start_tag: None,
+            elements: Vec::new(),
+            root: false,
+            skip_end_tag: false,
+            variant_attribute: None,
+            map_key_attribute: None,
+            none_representation: NoneRepresentation::EmptyElement,
+            bool_as_presence: false,
+            presence_field: false,
+            variant_content: false,
+            markup: false,
+            note: None,
+            cdata: false,
+        }
+    }
+
+    /// Writes the variant of an enum as an attribute, such as `type` or `xsi:type`, of the
+    /// element holding the enum instead of as a child element. This mirrors
+    /// `Deserializer::variant_attribute`.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Serialize;
+    /// # use serde_xml_rs::Serializer;
+    /// #[derive(Serialize)]
+    /// enum Shape {
+    ///     #[serde(rename = "circle")]
+    ///     Circle { r: f64 },
+    /// }
+    ///
+    /// #[derive(Serialize)]
+    /// struct Drawing {
+    ///     shape: Shape,
+    /// }
+    /// # fn main() {
+    /// let mut buffer = Vec::new();
+    /// let drawing = Drawing { shape: Shape::Circle { r: 3.0 } };
+    /// drawing.serialize(&mut Serializer::new(&mut buffer).variant_attribute("type")).unwrap();
+    ///
+    /// let serialized = String::from_utf8(buffer).unwrap();
+    /// assert_eq!(serialized, r#"<Drawing><shape type="circle"><r>3</r></shape></Drawing>"#);
+    /// # }
+    /// ```
+    pub fn variant_attribute(mut self, name: &str) -> Self {
+        self.variant_attribute = Some(name.to_string());
+        self
+    }
+
+    /// Writes a map held by a field as repeated elements named after the field, one per entry,
+    /// with the key in the given attribute and the value as content. An empty map leaves no
+    /// element at all. This mirrors `Deserializer::map_key_attribute`.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use std::collections::BTreeMap;
+    /// # use serde::Serialize;
+    /// # use serde_xml_rs::Serializer;
+    /// #[derive(Serialize)]
+    /// struct Config {
+    ///     setting: BTreeMap<String, u32>,
+    /// }
+    /// # fn main() {
+    /// let mut buffer = Vec::new();
+    /// let mut config = Config { setting: BTreeMap::new() };
+    /// config.setting.insert("retries".to_string(), 5);
+    /// config.setting.insert("timeout".to_string(), 30);
+    /// config.serialize(&mut Serializer::new(&mut buffer).map_key_attribute("key")).unwrap();
+    ///
+    /// let serialized = String::from_utf8(buffer).unwrap();
+    /// assert_eq!(
+    ///     serialized,
+    ///     r#"<Config><setting key="retries">5</setting><setting key="timeout">30</setting></Config>"#
+    /// );
+    /// # }
+    /// ```
+    pub fn map_key_attribute(mut self, name: &str) -> Self {
+        self.map_key_attribute = Some(name.to_string());
+        self
+    }
+
+    /// Chooses how fields holding `None` are written.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Serialize;
+    /// # use serde_xml_rs::Serializer;
+    /// # use serde_xml_rs::ser::NoneRepresentation;
+    /// #[derive(Serialize)]
+    /// struct Person {
+    ///     name: String,
+    ///     age: Option<u32>,
+    /// }
+    ///
+    /// # fn main() {
+    /// let mut buffer = Vec::new();
+    /// let joe = Person { name: "Joe".to_string(), age: None };
+    /// let mut ser = Serializer::new(&mut buffer).none_representation(NoneRepresentation::Omit);
+    /// joe.serialize(&mut ser).unwrap();
+    ///
+    /// let serialized = String::from_utf8(buffer).unwrap();
+    /// assert_eq!(serialized, "<Person><name>Joe</name></Person>");
+    /// # }
+    /// ```
+    pub fn none_representation(mut self, representation: NoneRepresentation) -> Self {
+        self.none_representation = representation;
+        self
+    }
+
+    /// Writes fields holding `true` as empty elements, such as `<required/>`, and leaves out the
+    /// ones holding `false`. A `bool` in an `Option` or a sequence is still written as text.
+    /// This mirrors `Deserializer::bool_as_presence`.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Serialize;
+    /// # use serde_xml_rs::Serializer;
+    /// #[derive(Serialize)]
+    /// struct Param {
+    ///     name: String,
+    ///     required: bool,
+    ///     deprecated: bool,
+    /// }
+    ///
+    /// # fn main() {
+    /// let mut buffer = Vec::new();
+    /// let param = Param { name: "id".to_string(), required: true, deprecated: false };
+    /// param.serialize(&mut Serializer::new(&mut buffer).bool_as_presence(true)).unwrap();
+    ///
+    /// let serialized = String::from_utf8(buffer).unwrap();
+    /// assert_eq!(serialized, "<Param><name>id</name><required/></Param>");
+    /// # }
+    /// ```
+    pub fn bool_as_presence(mut self, set: bool) -> Self {
+        self.bool_as_presence = set;
+        self
+    }
+
+    /// Writes the document in the given encoding, starting with an XML declaration that names
+    /// it. Characters the encoding can't represent are written as numeric character references
+    /// in text and attribute values, and between two CDATA sections in CDATA. Anywhere else, such
+    /// as in names and comments, they fail the serialization.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Serialize;
+    /// # use serde_xml_rs::{Encoding, Serializer};
+    /// #[derive(Serialize)]
+    /// struct Payment {
+    ///     payee: String,
+    ///     amount: String,
+    /// }
+    ///
+    /// # fn main() {
+    /// let mut buffer = Vec::new();
+    /// let payment = Payment { payee: "Ren\u{e9}e".to_string(), amount: "\u{20ac}50".to_string() };
+    /// payment.serialize(&mut Serializer::new(&mut buffer).encoding(Encoding::Latin1)).unwrap();
+    ///
+    /// assert_eq!(
+    ///     buffer,
+    ///     &b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>\
+    ///        <Payment><payee>Ren\xe9e</payee><amount>&#x20AC;50</amount></Payment>"[..]
+    /// );
+    /// # }
+    /// ```
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.writer.set_encoding(encoding);
+        self
+    }
+
+    /// Writes a comment, such as a "generated, do not edit" header before the root element.
+    /// The text can't contain `--` or end with `-`, which would end the comment early or
+    /// leave it malformed.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Serialize;
+    /// # use serde_xml_rs::Serializer;
+    /// #[derive(Serialize)]
+    /// struct Catalog {
+    ///     title: String,
+    /// }
+    ///
+    /// # fn main() {
+    /// let mut buffer = Vec::new();
+    /// {
+    ///     let mut ser = Serializer::new(&mut buffer);
+    ///     ser.write_processing_instruction("xml-stylesheet", Some("href=\"catalog.xsl\"")).unwrap();
+    ///     ser.write_comment(" generated, do not edit ").unwrap();
+    ///     Catalog { title: "Spring".to_string() }.serialize(&mut ser).unwrap();
+    /// }
+    ///
+    /// let serialized = String::from_utf8(buffer).unwrap();
+    /// assert_eq!(
+    ///     serialized,
+    ///     "<?xml-stylesheet href=\"catalog.xsl\"?><!-- generated, do not edit -->\
+    ///      <Catalog><title>Spring</title></Catalog>"
+    /// );
+    /// # }
+    /// ```
+    pub fn write_comment(&mut self, text: &str) -> Result<()> {
+        check_comment(text)?;
+        self.close_start_tag()?;
+        write!(self.writer, "<!--{}-->", text)?;
+        Ok(())
+    }
+
+    /// Writes a processing instruction, such as `<?xml-stylesheet href="style.xsl"?>`. The
+    /// target has to be a name other than `xml`, and the data can't contain `?>`.
+    pub fn write_processing_instruction(&mut self, target: &str, data: Option<&str>) -> Result<()> {
+        check_processing_instruction(target, data)?;
+        self.close_start_tag()?;
+        match data {
+            Some(data) => Ok(write!(self.writer, "<?{} {}?>", target, data)?),
+            None => Ok(write!(self.writer, "<?{}?>", target)?),
+        }
+    }
+
+    /// Writes the start tag of an element, whose content can then be serialized one item at a
+    /// time, such as the rows of an export that are never all in memory at once. Each item is
+    /// written the same way `to_writer` would write it.
+    ///
+    /// ```rust
+    /// # #[macro_use]
+    /// # extern crate serde_derive;
+    /// # extern crate serde;
+    /// # extern crate serde_xml_rs;
+    /// # use serde::Serialize;
+    /// # use serde_xml_rs::Serializer;
+    /// #[derive(Serialize)]
+    /// struct Row {
+    ///     id: u32,
+    /// }
+    ///
+    /// # fn main() {
+    /// let mut buffer = Vec::new();
+    /// {
+    ///     let mut ser = Serializer::new(&mut buffer);
+    ///     ser.start_element("rows").unwrap();
+    ///     for id in 1..4 {
+    ///         Row { id }.serialize(&mut ser).unwrap();
+    ///         if id % 2 == 0 {
+    ///             ser.flush().unwrap();
+    ///         }
+    ///     }
+    ///     ser.end_element().unwrap();
+    ///     ser.end().unwrap();
+    ///     ser.flush().unwrap();
+    /// }
+    ///
+    /// let serialized = String::from_utf8(buffer).unwrap();
+    /// assert_eq!(
+    ///     serialized,
+    ///     "<rows><Row><id>1</id></Row><Row><id>2</id></Row><Row><id>3</id></Row></rows>"
+    /// );
+    /// # }
+    /// ```
+    pub fn start_element(&mut self, name: &str) -> Result<()> {
+        self.open_tag(name)?;
+        self.close_start_tag()
+    }
+
+    /// Writes the end tag of the element started last by `start_element`.
+    pub fn end_element(&mut self) -> Result<()> {
+        if self.elements.is_empty() {
+            let message = "there is no element left to end";
+            return Err(ErrorKind::Custom(message.to_string()).into());
+        }
+        self.end_tag()
+    }
+
+    /// Checks that the document written so far is complete: it has a root element and every
+    /// element started by `start_element` has ended. `to_writer` and `to_string` do this once
+    /// the value has been written.
+    pub fn end(&mut self) -> Result<()> {
+        if let Some(name) = self.elements.last() {
+            return Err(ErrorKind::UnclosedElement(name.clone()).into());
+        }
+        if !self.root {
+            return Err(ErrorKind::MissingRoot.into());
+        }
+        Ok(())
+    }
+
+    /// Passes what has been written so far on to the underlying writer.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
     }
 
     fn write_primitive<P: Display>(&mut self, primitive: P) -> Result<()> {
+        if self.elements.is_empty() {
+            if !self.markup {
+                // Text can only go inside the root element.
+                return Err(ErrorKind::MissingRoot.into());
+            }
+            // The markup is taken to hold the root element.
+            self.start_root("markup")?;
+        }
+        self.close_start_tag()?;
         write!(self.writer, "{}", primitive)?;
         Ok(())
     }
 
     fn write_wrapped<S: Serialize>(&mut self, tag: &str, value: S) -> Result<()> {
-        write!(self.writer, "<{}>", tag)?;
+        self.open_tag(tag)?;
         value.serialize(&mut *self)?;
-        write!(self.writer, "</{}>", tag)?;
+        self.end_tag()
+    }
+
+    /// Makes sure a document only gets one root element.
+    fn start_root(&mut self, found: &str) -> Result<()> {
+        if self.root {
+            return Err(ErrorKind::MultipleRoots(found.to_string()).into());
+        }
+        self.root = true;
+        Ok(())
+    }
+
+    /// Starts an element whose start tag is held back until the content of the element follows.
+    fn open_tag(&mut self, name: &str) -> Result<()> {
+        self.presence_field = false;
+        self.variant_content = false;
+        if !is_name(name) {
+            return Err(ErrorKind::InvalidName(name.to_string()).into());
+        }
+        if self.elements.is_empty() {
+            self.start_root(name)?;
+        }
+        self.close_start_tag()?;
+        self.start_tag = Some(format!("<{}", name));
+        self.elements.push(name.to_string());
+        Ok(())
+    }
+
+    /// Renames the element that was started last, as long as its start tag is still held back.
+    fn rename_element(&mut self, name: &str) -> Result<()> {
+        let start_tag = match self.start_tag.as_mut() {
+            Some(start_tag) => start_tag,
+            None => {
+                let message = "the `$name` field has to come before the content of the element";
+                return Err(ErrorKind::Custom(message.to_string()).into());
+            },
+        };
+        if !is_name(name) {
+            return Err(ErrorKind::InvalidName(name.to_string()).into());
+        }
+        let old = debug_expect!(self.elements.pop(), Some(old) => old);
+        *start_tag = format!("<{}{}", name, &start_tag[1 + old.len()..]);
+        self.elements.push(name.to_string());
+        Ok(())
+    }
+
+    fn close_start_tag(&mut self) -> Result<()> {
+        if let Some(start_tag) = self.start_tag.take() {
+            write!(self.writer, "{}>", start_tag)?;
+        }
         Ok(())
     }
+
+    fn end_tag(&mut self) -> Result<()> {
+        let name = debug_expect!(self.elements.pop(), Some(name) => name);
+        if ::std::mem::replace(&mut self.skip_end_tag, false) {
+            return Ok(());
+        }
+        self.close_start_tag()?;
+        write!(self.writer, "</{}>", name)?;
+        Ok(())
+    }
+
+    fn write_attribute(&mut self, name: &str, value: &str) -> Result<()> {
+        if !is_name(name) {
+            return Err(ErrorKind::InvalidName(name.to_string()).into());
+        }
+        debug_expect!(self.start_tag.as_mut(), Some(start_tag) => {
+            start_tag.push_str(&format!(" {}=\"{}\"", name, escape_str_attribute(value)));
+            Ok(())
+        })
+    }
+
+    /// Names the variant of an enum with the attribute set by `variant_attribute`, adding it to
+    /// the element holding the enum or to a new element named after the enum. Returns whether
+    /// a new element was started, which the caller then has to end.
+    fn write_variant_attribute(&mut self, name: &str, variant: &str) -> Result<bool> {
+        let attribute = match self.variant_attribute.clone() {
+            Some(attribute) => attribute,
+            None => return Ok(false),
+        };
+        let new_element = self.start_tag.is_none();
+        if new_element {
+            self.open_tag(name)?;
+        }
+        if attribute.starts_with("xsi:") {
+            self.write_attribute("xmlns:xsi", XSI_NAMESPACE)?;
+        }
+        self.write_attribute(&attribute, variant)?;
+        Ok(new_element)
+    }
 }
 
 
@@ -112,22 +605,25 @@ where
     type Ok = ();
     type Error = Error;
 
-    type SerializeSeq = Impossible<Self::Ok, Self::Error>;
+    type SerializeSeq = Seq<'w, W>;
     type SerializeTuple = Impossible<Self::Ok, Self::Error>;
     type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
-    type SerializeTupleVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleVariant = OtherVariant<'w, W>;
     type SerializeMap = Map<'w, W>;
     type SerializeStruct = Struct<'w, W>;
-    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = Struct<'w, W>;
 
     fn serialize_bool(self, v: bool) -> Result<Self::Ok> {
-        if v {
-            write!(self.writer, "true")?;
-        } else {
-            write!(self.writer, "false")?;
+        if ::std::mem::replace(&mut self.presence_field, false) {
+            if let Some(start_tag) = self.start_tag.take() {
+                if v {
+                    write!(self.writer, "{}/>", start_tag)?;
+                }
+                self.skip_end_tag = true;
+                return Ok(());
+            }
         }
-
-        Ok(())
+        self.write_primitive(v)
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
@@ -171,11 +667,27 @@ where
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok> {
-        self.write_primitive(v)
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
     }
 
     fn serialize_str(self, value: &str) -> Result<Self::Ok> {
-        self.write_primitive(value)
+        match self.note {
+            Some(Note::Comment) => return self.write_comment(value),
+            Some(Note::ProcessingInstruction) => {
+                // The target is followed by the data, as in a `Node::ProcessingInstruction`.
+                let mut parts = value.splitn(2, char::is_whitespace);
+                let target = parts.next().unwrap_or("");
+                return self.write_processing_instruction(target, parts.next());
+            },
+            None => {},
+        }
+        if self.cdata {
+            self.write_primitive(cdata_section(value))
+        } else if self.markup {
+            self.write_primitive(value)
+        } else {
+            self.write_primitive(escape_str_pcdata(value))
+        }
     }
 
     fn serialize_bytes(self, value: &[u8]) -> Result<Self::Ok> {
@@ -187,15 +699,31 @@ where
     }
 
     fn serialize_none(self) -> Result<Self::Ok> {
+        if self.start_tag.is_none() {
+            return Ok(());
+        }
+        match self.none_representation {
+            NoneRepresentation::Omit => {
+                self.start_tag = None;
+                self.skip_end_tag = true;
+            },
+            NoneRepresentation::EmptyElement => {},
+            NoneRepresentation::XsiNil => {
+                self.write_attribute("xmlns:xsi", XSI_NAMESPACE)?;
+                self.write_attribute("xsi:nil", "true")?;
+            },
+        }
         Ok(())
     }
 
     fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Self::Ok> {
+        // `Some(false)` has to be told apart from `None`.
+        self.presence_field = false;
         value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<Self::Ok> {
-        self.serialize_none()
+        Ok(())
     }
 
     fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok> {
@@ -208,9 +736,15 @@ where
         variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok> {
-        Err(
-            ErrorKind::UnsupportedOperation("serialize_unit_variant".to_string()).into(),
-        )
+        if self.variant_attribute.is_none() {
+            return Err(
+                ErrorKind::UnsupportedOperation("serialize_unit_variant".to_string()).into(),
+            );
+        }
+        if self.write_variant_attribute(name, variant)? {
+            self.end_tag()?;
+        }
+        Ok(())
     }
 
     fn serialize_newtype_struct<T: ?Sized + Serialize>(
@@ -218,9 +752,28 @@ where
         name: &'static str,
         value: &T,
     ) -> Result<Self::Ok> {
-        Err(
-            ErrorKind::UnsupportedOperation("serialize_newtype_struct".to_string()).into(),
-        )
+        if name == MARKUP_NEWTYPE || name == RAW_MARKUP_NEWTYPE {
+            // An `Element` checks itself before its markup gets here, while the markup of a
+            // `RawXml` is written unchecked.
+            if name == RAW_MARKUP_NEWTYPE {
+                self.close_start_tag()?;
+            } else if self.start_tag.take().is_some() {
+                // The markup holds a whole element, which takes the place of the one started
+                // for it.
+                self.skip_end_tag = true;
+            }
+            self.markup = true;
+            let result = value.serialize(&mut *self);
+            self.markup = false;
+            return result;
+        }
+        if name == CDATA_NEWTYPE {
+            self.cdata = true;
+            let result = value.serialize(&mut *self);
+            self.cdata = false;
+            return result;
+        }
+        value.serialize(self)
     }
 
     fn serialize_newtype_variant<T: ?Sized + Serialize>(
@@ -230,14 +783,35 @@ where
         variant: &'static str,
         value: &T,
     ) -> Result<Self::Ok> {
-        self.write_wrapped(variant, value)
+        self.presence_field = false;
+        if variant == OTHER_VARIANT {
+            // The value is an element of its own, such as an `Element`, which goes inside the
+            // element holding the enum like any other variant.
+            self.close_start_tag()?;
+            return value.serialize(self);
+        }
+        if self.variant_attribute.is_none() {
+            return self.write_wrapped(variant, value);
+        }
+        let new_element = self.write_variant_attribute(name, variant)?;
+        self.variant_content = true;
+        let result = value.serialize(&mut *self);
+        self.variant_content = false;
+        result?;
+        if new_element {
+            self.end_tag()?;
+        }
+        Ok(())
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        // TODO: Figure out how to constrain the things written to only be composites
-        Err(
-            ErrorKind::UnsupportedOperation("serialize_seq".to_string()).into(),
-        )
+        if self.start_tag.take().is_some() {
+            // Every item gets an element like the one started for the sequence, which is left
+            // out.
+            let name = debug_expect!(self.elements.last(), Some(name) => name.clone());
+            return Ok(Seq::repeated(self, name));
+        }
+        Ok(Seq::new(self))
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
@@ -263,18 +837,33 @@ where
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
+        if variant == OTHER_VARIANT {
+            return Ok(OtherVariant::new(self));
+        }
         Err(
             ErrorKind::UnsupportedOperation("serialize_tuple_variant".to_string()).into(),
         )
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
+        if let Some(attribute) = self.map_key_attribute.clone() {
+            if self.start_tag.take().is_some() {
+                // Every entry gets an element like the one started for the map, which is left
+                // out.
+                let name = debug_expect!(self.elements.last(), Some(name) => name.clone());
+                return Ok(Map::keyed(self, name, attribute));
+            }
+        }
         Ok(Map::new(self))
     }
 
     fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        write!(self.writer, "<{}>", name)?;
-        Ok(Struct::new(self, name))
+        if ::std::mem::replace(&mut self.variant_content, false) && self.start_tag.is_some() {
+            // The fields go straight into the element that holds the enum.
+            return Ok(Struct::new_content(self));
+        }
+        self.open_tag(name)?;
+        Ok(Struct::new(self))
     }
 
     fn serialize_struct_variant(
@@ -284,7 +873,15 @@ where
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        Err(ErrorKind::UnsupportedOperation("Result".to_string()).into())
+        if self.variant_attribute.is_none() {
+            self.open_tag(variant)?;
+            return Ok(Struct::new(self));
+        }
+        if self.write_variant_attribute(name, variant)? {
+            Ok(Struct::new(self))
+        } else {
+            Ok(Struct::new_content(self))
+        }
     }
 }
 
@@ -294,17 +891,20 @@ mod tests {
     use super::*;
     use serde::Serializer as SerSerializer;
     use serde::ser::{SerializeMap, SerializeStruct};
+    use value::{Element, RawXml};
 
     #[test]
     fn test_serialize_bool() {
-        let inputs = vec![(true, "true"), (false, "false")];
+        let inputs = vec![(true, "<b>true</b>"), (false, "<b>false</b>")];
 
         for (src, should_be) in inputs {
             let mut buffer = Vec::new();
 
             {
                 let mut ser = Serializer::new(&mut buffer);
+                ser.start_element("b").unwrap();
                 ser.serialize_bool(src).unwrap();
+                ser.end_element().unwrap();
             }
 
             let got = String::from_utf8(buffer).unwrap();
@@ -318,11 +918,12 @@ mod tests {
 
         {
             let mut ser = Serializer::new(&mut buffer);
-            let _ = ser.serialize_struct("foo", 0).unwrap();
+            let struct_ser = ser.serialize_struct("foo", 0).unwrap();
+            struct_ser.end().unwrap();
         }
 
         let got = String::from_utf8(buffer).unwrap();
-        assert_eq!(got, "<foo>");
+        assert_eq!(got, "<foo></foo>");
     }
 
     #[test]
@@ -331,7 +932,7 @@ mod tests {
 
         {
             let mut ser = Serializer::new(&mut buffer);
-            let mut struct_ser = Struct::new(&mut ser, "baz");
+            let mut struct_ser = Struct::new(&mut ser);
             struct_ser.serialize_field("foo", "bar").unwrap();
         }
 
@@ -365,20 +966,109 @@ mod tests {
 
     #[test]
     fn test_serialize_map_entries() {
-        let should_be = "<name>Bob</name><age>5</age>";
+        let should_be = "<map><name>Bob</name><age>5</age></map>";
         let mut buffer = Vec::new();
 
         {
             let mut ser = Serializer::new(&mut buffer);
-            let mut map = Map::new(&mut ser);
-            map.serialize_entry("name", "Bob").unwrap();
-            map.serialize_entry("age", "5").unwrap();
+            ser.start_element("map").unwrap();
+            {
+                let mut map = Map::new(&mut ser);
+                map.serialize_entry("name", "Bob").unwrap();
+                map.serialize_entry("age", "5").unwrap();
+            }
+            ser.end_element().unwrap();
         }
 
         let got = String::from_utf8(buffer).unwrap();
         assert_eq!(got, should_be);
     }
 
+    #[test]
+    fn test_serialize_map_rejects_composite_keys() {
+        #[derive(Serialize)]
+        struct Point {
+            x: i32,
+        }
+
+        let mut buffer = Vec::new();
+        let mut ser = Serializer::new(&mut buffer);
+        ser.start_element("map").unwrap();
+        let mut map = Map::new(&mut ser);
+        map.serialize_entry(&'a', "one").unwrap();
+        map.serialize_entry(&true, "yes").unwrap();
+        assert!(map.serialize_entry(&Point { x: 1 }, "point").is_err());
+        assert!(map.serialize_entry(&vec!["a"], "list").is_err());
+    }
+
+    #[test]
+    fn test_serialize_rejects_malformed_documents() {
+        use std::collections::BTreeMap;
+
+        #[derive(Serialize)]
+        struct Point {
+            x: i32,
+        }
+
+        #[derive(Serialize)]
+        struct Labels {
+            label: BTreeMap<String, u32>,
+        }
+
+        match *to_string(&5).unwrap_err().kind() {
+            ErrorKind::MissingRoot => {},
+            ref e => panic!("unexpected error: {}", e),
+        }
+        match *to_string(&()).unwrap_err().kind() {
+            ErrorKind::MissingRoot => {},
+            ref e => panic!("unexpected error: {}", e),
+        }
+        match *to_string(&vec![Point { x: 1 }, Point { x: 2 }]).unwrap_err().kind() {
+            ErrorKind::MultipleRoots(ref found) => assert_eq!(found, "Point"),
+            ref e => panic!("unexpected error: {}", e),
+        }
+
+        let mut labels = Labels { label: BTreeMap::new() };
+        labels.label.insert("two words".to_string(), 2);
+        match *to_string(&labels).unwrap_err().kind() {
+            ErrorKind::InvalidName(ref name) => assert_eq!(name, "two words"),
+            ref e => panic!("unexpected error: {}", e),
+        }
+
+        let mut points = BTreeMap::new();
+        points.insert(vec![1], Point { x: 1 });
+        match *to_string(&points).unwrap_err().kind() {
+            ErrorKind::NotScalar(_) => {},
+            ref e => panic!("unexpected error: {}", e),
+        }
+
+        let mut ser = Serializer::new(Vec::new());
+        ser.start_element("rows").unwrap();
+        match *ser.end().unwrap_err().kind() {
+            ErrorKind::UnclosedElement(ref name) => assert_eq!(name, "rows"),
+            ref e => panic!("unexpected error: {}", e),
+        }
+
+        let error = to_string(&Element::new("bad name")).unwrap_err();
+        assert!(error.to_string().contains("invalid XML name: 'bad name'"), "{}", error);
+        assert_eq!(to_string(&RawXml("<bad name/>".to_string())).unwrap(), "<bad name/>");
+    }
+
+    #[test]
+    fn test_serialize_str_escapes_text() {
+        let mut buffer = Vec::new();
+
+        {
+            let mut ser = Serializer::new(&mut buffer);
+            ser.start_element("dish").unwrap();
+            ser.serialize_str("<b>Fish & Chips</b>").unwrap();
+            ser.end_element().unwrap();
+        }
+
+        let got = String::from_utf8(buffer).unwrap();
+        assert_eq!(got, "<dish>&lt;b&gt;Fish &amp; Chips&lt;/b&gt;</dish>");
+    }
+
     #[test]
     fn test_serialize_enum() {
         #[derive(Serialize)]
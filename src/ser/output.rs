@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+use std::str;
+
+use encoding::Encoding;
+
+/// Wraps the writer of a `Serializer` to turn what is written into the chosen encoding, which
+/// is declared at the start of the document.
+///
+/// Characters the encoding can't represent are written as numeric character references, which
+/// are only allowed in text and attribute values. To know where it is, the output keeps track of
+/// the markup it has seen.
+pub struct Output<W: Write> {
+    inner: W,
+    encoding: Option<Encoding>,
+    started: bool,
+    buf: Vec<u8>,
+    context: Context,
+    /// The last few characters written, to recognize the delimiters of the markup.
+    recent: String,
+}
+
+/// Where in the document the output is.
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    Text,
+    /// Within a tag, and within an attribute value if it has its quote.
+    Tag(Option<char>),
+    CData,
+    Comment,
+    ProcessingInstruction,
+}
+
+impl<W: Write> Output<W> {
+    pub fn new(inner: W) -> Self {
+        Output {
+            inner,
+            encoding: None,
+            started: false,
+            buf: Vec::new(),
+            context: Context::Text,
+            recent: String::new(),
+        }
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = Some(encoding);
+    }
+
+    /// Moves on to the context that `c`, written after the recent characters, leads to.
+    fn advance(&mut self, c: char) {
+        if self.recent.len() >= 9 {
+            self.recent.remove(0);
+        }
+        self.recent.push(c);
+        let ends_with = |delimiter: &str| self.recent.ends_with(delimiter);
+        self.context = match self.context {
+            Context::Text if c == '<' => Context::Tag(None),
+            Context::Tag(None) if ends_with("<![CDATA[") => Context::CData,
+            Context::Tag(None) if ends_with("<!--") => Context::Comment,
+            Context::Tag(None) if ends_with("<?") => Context::ProcessingInstruction,
+            Context::Tag(None) if c == '"' || c == '\'' => Context::Tag(Some(c)),
+            Context::Tag(None) if c == '>' => Context::Text,
+            Context::Tag(Some(quote)) if c == quote => Context::Tag(None),
+            Context::CData if ends_with("]]>") => Context::Text,
+            Context::Comment if ends_with("-->") => Context::Text,
+            Context::ProcessingInstruction if ends_with("?>") => Context::Text,
+            context => context,
+        };
+    }
+}
+
+impl<W: Write> Write for Output<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => return self.inner.write(buf),
+        };
+        let text = str::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        self.buf.clear();
+        if !self.started {
+            self.started = true;
+            // UTF-16 needs a byte order mark, UTF-8 is better off without one.
+            if encoding != Encoding::Utf8 {
+                self.buf.extend_from_slice(encoding.bom());
+            }
+            let declaration = format!("<?xml version=\"1.0\" encoding=\"{}\"?>", encoding.name());
+            for c in declaration.chars() {
+                encoding.encode_char(c, &mut self.buf);
+            }
+        }
+        for c in text.chars() {
+            if !encoding.encode_char(c, &mut self.buf) {
+                let reference = format!("&#x{:X};", c as u32);
+                let replacement = match self.context {
+                    Context::Text | Context::Tag(Some(_)) => reference,
+                    // The reference goes between two CDATA sections, where it's text.
+                    Context::CData => format!("]]>{}<![CDATA[", reference),
+                    _ => {
+                        let message = format!("{:?} can't be written in {} here", c, encoding.name());
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                    },
+                };
+                for r in replacement.chars() {
+                    encoding.encode_char(r, &mut self.buf);
+                }
+            }
+            self.advance(c);
+        }
+        self.inner.write_all(&self.buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -2,8 +2,18 @@ use std::io::Write;
 
 use serde::ser::{self, Serialize};
 
-use ser::Serializer;
-use error::{Error, Result};
+use ser::{Note, Serializer};
+use ser::key::to_text;
+use error::{Error, ErrorKind, Result};
+
+/// Serializes a value that names an element. Returns `None` when it comes out empty.
+fn element_name<T: ?Sized + Serialize>(value: &T) -> Result<Option<String>> {
+    let name = to_text(value)?;
+    if name.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(name))
+}
 
 /// An implementation of `SerializeMap` for serializing to XML.
 pub struct Map<'w, W>
@@ -11,6 +21,9 @@ where
     W: 'w + Write,
 {
     parent: &'w mut Serializer<W>,
+    /// The name of the elements written for the entries and the attribute holding their keys,
+    /// for maps written with `Serializer::map_key_attribute`.
+    keyed: Option<(String, String)>,
 }
 
 impl<'w, W> Map<'w, W>
@@ -18,7 +31,18 @@ where
     W: 'w + Write,
 {
     pub fn new(parent: &'w mut Serializer<W>) -> Map<'w, W> {
-        Map { parent }
+        Map {
+            parent,
+            keyed: None,
+        }
+    }
+
+    /// Writes each entry as an element with the given name, keyed by the given attribute.
+    pub fn keyed(parent: &'w mut Serializer<W>, name: String, attribute: String) -> Map<'w, W> {
+        Map {
+            parent,
+            keyed: Some((name, attribute)),
+        }
     }
 }
 
@@ -38,6 +62,10 @@ where
     }
 
     fn end(self) -> Result<Self::Ok> {
+        if self.keyed.is_some() {
+            // The element started for the map was left out.
+            self.parent.skip_end_tag = true;
+        }
         Ok(())
     }
 
@@ -46,18 +74,13 @@ where
         key: &K,
         value: &V,
     ) -> Result<()> {
-        // TODO: Is it possible to ensure our key is never a composite type?
-        // Anything which isn't a "primitive" would lead to malformed XML here...
-        write!(self.parent.writer, "<")?;
-        key.serialize(&mut *self.parent)?;
-        write!(self.parent.writer, ">")?;
-
-        value.serialize(&mut *self.parent)?;
-
-        write!(self.parent.writer, "</")?;
-        key.serialize(&mut *self.parent)?;
-        write!(self.parent.writer, ">")?;
-        Ok(())
+        if let Some((ref name, ref attribute)) = self.keyed {
+            self.parent.open_tag(name)?;
+            self.parent.write_attribute(attribute, &to_text(key)?)?;
+            value.serialize(&mut *self.parent)?;
+            return self.parent.end_tag();
+        }
+        self.parent.write_wrapped(&to_text(key)?, value)
     }
 }
 
@@ -67,15 +90,29 @@ where
     W: 'w + Write,
 {
     parent: &'w mut Serializer<W>,
-    name: &'w str,
+    /// Set when the struct started an element of its own, rather than reusing the one holding
+    /// it.
+    ends_element: bool,
 }
 
 impl<'w, W> Struct<'w, W>
 where
     W: 'w + Write,
 {
-    pub fn new(parent: &'w mut Serializer<W>, name: &'w str) -> Struct<'w, W> {
-        Struct { parent, name }
+    /// Serializes the fields into the element that was started for the struct, and ends it.
+    pub fn new(parent: &'w mut Serializer<W>) -> Struct<'w, W> {
+        Struct {
+            parent,
+            ends_element: true,
+        }
+    }
+
+    /// Serializes the fields into the element that is currently open.
+    pub fn new_content(parent: &'w mut Serializer<W>) -> Struct<'w, W> {
+        Struct {
+            parent,
+            ends_element: false,
+        }
     }
 }
 
@@ -91,13 +128,168 @@ where
         key: &'static str,
         value: &T,
     ) -> Result<()> {
-        write!(self.parent.writer, "<{}>", key)?;
-        value.serialize(&mut *self.parent)?;
-        write!(self.parent.writer, "</{}>", key)?;
+        if key == "$name" {
+            return match element_name(value)? {
+                Some(name) => self.parent.rename_element(&name),
+                None => Ok(()),
+            };
+        }
+        if key == "$comments" || key == "$processing_instructions" {
+            // Every string in the value is written as a comment or processing instruction.
+            self.parent.close_start_tag()?;
+            self.parent.note = Some(if key == "$comments" {
+                Note::Comment
+            } else {
+                Note::ProcessingInstruction
+            });
+            let result = value.serialize(&mut *self.parent);
+            self.parent.note = None;
+            return result;
+        }
+        if key == "$value" {
+            // The content goes straight into the element of the struct.
+            self.parent.close_start_tag()?;
+            return value.serialize(&mut *self.parent);
+        }
+        if self.parent.bool_as_presence {
+            self.parent.open_tag(key)?;
+            self.parent.presence_field = true;
+            value.serialize(&mut *self.parent)?;
+            self.parent.presence_field = false;
+            return self.parent.end_tag();
+        }
+        self.parent.write_wrapped(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        if self.ends_element {
+            self.parent.end_tag()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<'w, W> ser::SerializeStructVariant for Struct<'w, W>
+where
+    W: 'w + Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        ser::SerializeStruct::end(self)
+    }
+}
+
+/// An implementation of `SerializeTupleVariant` for the `$other` variant of an enum, whose first
+/// field names the element that the other fields are written into.
+pub struct OtherVariant<'w, W>
+where
+    W: 'w + Write,
+{
+    parent: &'w mut Serializer<W>,
+    started: bool,
+}
+
+impl<'w, W> OtherVariant<'w, W>
+where
+    W: 'w + Write,
+{
+    pub fn new(parent: &'w mut Serializer<W>) -> OtherVariant<'w, W> {
+        OtherVariant {
+            parent,
+            started: false,
+        }
+    }
+}
+
+impl<'w, W> ser::SerializeTupleVariant for OtherVariant<'w, W>
+where
+    W: 'w + Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        if self.started {
+            return value.serialize(&mut *self.parent);
+        }
+        match element_name(value)? {
+            Some(name) => self.parent.open_tag(&name)?,
+            None => return Err(ErrorKind::Custom("missing element name".to_string()).into()),
+        }
+        self.started = true;
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok> {
-        write!(self.parent.writer, "</{}>", self.name).map_err(|e| e.into())
+        if self.started {
+            self.parent.end_tag()
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// An implementation of `SerializeSeq` for serializing to XML.
+pub struct Seq<'w, W>
+where
+    W: 'w + Write,
+{
+    parent: &'w mut Serializer<W>,
+    /// The name of the element each item is wrapped in, for a sequence held by a field.
+    repeated: Option<String>,
+}
+
+impl<'w, W> Seq<'w, W>
+where
+    W: 'w + Write,
+{
+    /// Writes the items one after the other, as the content of the element that is open.
+    pub fn new(parent: &'w mut Serializer<W>) -> Seq<'w, W> {
+        Seq {
+            parent,
+            repeated: None,
+        }
+    }
+
+    /// Writes each item in an element with the given name.
+    pub fn repeated(parent: &'w mut Serializer<W>, name: String) -> Seq<'w, W> {
+        Seq {
+            parent,
+            repeated: Some(name),
+        }
+    }
+}
+
+impl<'w, W> ser::SerializeSeq for Seq<'w, W>
+where
+    W: 'w + Write,
+{
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        match self.repeated {
+            Some(ref name) => self.parent.write_wrapped(name, value),
+            None => value.serialize(&mut *self.parent),
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok> {
+        if self.repeated.is_some() {
+            // The element started for the sequence was left out.
+            self.parent.skip_end_tag = true;
+        }
+        Ok(())
     }
 }
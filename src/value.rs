@@ -0,0 +1,423 @@
+//! A generic tree of XML elements, for documents whose structure isn't known in advance.
+//!
+//! ```rust
+//! # extern crate serde_xml_rs;
+//! # use serde_xml_rs::{from_str, Element, Node};
+//! # fn main() {
+//! let s = r##"<order id="7"><!-- rush --><item>Apple</item><item>Pear</item></order>"##;
+//! let order: Element = from_str(s).unwrap();
+//!
+//! assert_eq!(order.name, "order");
+//! assert_eq!(order.attribute("id"), Some("7"));
+//! assert_eq!(order.children[0], Node::Comment(" rush ".to_string()));
+//! let items: Vec<String> = order.elements().map(|item| item.text()).collect();
+//! assert_eq!(items, vec!["Apple", "Pear"]);
+//! assert_eq!(order.to_string(), s);
+//! # }
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Error as SerError, Serialize, Serializer};
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
+use xml::namespace::{Namespace, NS_XMLNS_PREFIX, NS_XML_PREFIX};
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+
+use error::{Error, ErrorKind, Result};
+use ser::{check_comment, check_processing_instruction, is_name};
+use {from_str, to_string, CDATA_NEWTYPE, MARKUP_NEWTYPE, RAW_MARKUP_NEWTYPE};
+
+/// An XML element with its attributes and everything it contains, in document order.
+///
+/// An `Element` can be deserialized from a whole element, by itself or as a field of a struct,
+/// and is serialized as the same element. A field holding an `Element` is written as that
+/// element, so the element should be named after the field. Other formats see an `Element` as
+/// a string of markup.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Element {
+    /// The name of the element, including any prefix such as `soap:`.
+    pub name: String,
+    /// The namespace the name of the element belongs to.
+    pub namespace: Option<String>,
+    /// The attributes of the element, starting with the namespaces it declares such as
+    /// `xmlns:soap`.
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+/// A piece of the content of an `Element`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+    CData(String),
+    Comment(String),
+    /// A processing instruction such as `<?app-version 3.2?>`, whose target is `app-version`.
+    ProcessingInstruction { target: String, data: Option<String> },
+}
+
+impl Element {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Element {
+            name: name.into(),
+            ..Element::default()
+        }
+    }
+
+    /// The value of the attribute with the given name, including any prefix.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attr, _)| attr == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The elements among the children of this element.
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match *child {
+            Node::Element(ref element) => Some(element),
+            _ => None,
+        })
+    }
+
+    /// The text and CDATA sections among the children of this element, joined together.
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|child| match *child {
+                Node::Text(ref text) | Node::CData(ref text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Writes the element, given the namespaces declared by its ancestors.
+    /// Checks that the element can be written as well-formed markup.
+    fn check(&self) -> Result<()> {
+        if !is_name(&self.name) {
+            return Err(ErrorKind::InvalidName(self.name.clone()).into());
+        }
+        if let Some((name, _)) = self.attributes.iter().find(|(name, _)| !is_name(name)) {
+            return Err(ErrorKind::InvalidName(name.clone()).into());
+        }
+        for child in &self.children {
+            match *child {
+                Node::Element(ref element) => element.check()?,
+                Node::Comment(ref text) => check_comment(text)?,
+                Node::ProcessingInstruction { ref target, ref data } => {
+                    check_processing_instruction(target, data.as_ref().map(String::as_str))?
+                },
+                Node::Text(_) | Node::CData(_) => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, scope: &[(&str, &str)]) -> fmt::Result {
+        let mut scope = scope.to_vec();
+        scope.extend(
+            self.attributes
+                .iter()
+                .filter(|(name, _)| name == "xmlns" || name.starts_with("xmlns:"))
+                .map(|(name, uri)| (name.as_str(), uri.as_str())),
+        );
+
+        write!(f, "<{}", self.name)?;
+        let declaration = match self.name.find(':') {
+            Some(i) => format!("xmlns:{}", &self.name[..i]),
+            None => "xmlns".to_string(),
+        };
+        if let Some(ref namespace) = self.namespace {
+            let declared = scope.iter().rev().find(|&&(name, _)| name == declaration);
+            if declared.map(|&(_, uri)| uri) != Some(namespace.as_str()) {
+                write!(f, " {}=\"{}\"", declaration, escape_str_attribute(namespace))?;
+                scope.push((&declaration, namespace));
+            }
+        }
+        for (name, value) in &self.attributes {
+            write!(f, " {}=\"{}\"", name, escape_str_attribute(value))?;
+        }
+        if self.children.is_empty() {
+            return write!(f, "/>");
+        }
+
+        write!(f, ">")?;
+        for child in &self.children {
+            match *child {
+                Node::Element(ref element) => element.write(f, &scope)?,
+                Node::Text(ref text) => write!(f, "{}", escape_str_pcdata(text))?,
+                Node::CData(ref text) => write!(f, "{}", cdata_section(text))?,
+                Node::Comment(ref text) => write!(f, "<!--{}-->", text)?,
+                Node::ProcessingInstruction { ref target, data: Some(ref data) } => {
+                    write!(f, "<?{} {}?>", target, data)?
+                },
+                Node::ProcessingInstruction { ref target, data: None } => {
+                    write!(f, "<?{}?>", target)?
+                },
+            }
+        }
+        write!(f, "</{}>", self.name)
+    }
+}
+
+/// Writes the element as markup.
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &[])
+    }
+}
+
+/// Reads an element from markup. Anything around the element, such as an XML declaration or
+/// comments, is left out, and so is whitespace between tags.
+impl FromStr for Element {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let config = ParserConfig::new()
+            .trim_whitespace(false)
+            .whitespace_to_characters(false)
+            .cdata_to_characters(false)
+            .ignore_comments(false)
+            .coalesce_characters(true);
+        let reader = EventReader::new_with_config(s.as_bytes(), config);
+
+        let mut stack: Vec<(Element, Namespace)> = Vec::new();
+        for event in reader {
+            let node = match event.map_err(ErrorKind::Syntax)? {
+                XmlEvent::StartElement { name, attributes, namespace } => {
+                    let mut element = Element::new(match name.prefix {
+                        Some(ref prefix) => format!("{}:{}", prefix, name.local_name),
+                        None => name.local_name.clone(),
+                    });
+                    element.namespace = name.namespace;
+                    element.attributes = {
+                        let parent = stack.last().map(|(_, scope)| scope);
+                        namespace_declarations(&namespace, parent)
+                    };
+                    element.attributes.extend(attributes.into_iter().map(|attr| {
+                        let name = match attr.name.prefix {
+                            Some(prefix) => format!("{}:{}", prefix, attr.name.local_name),
+                            None => attr.name.local_name,
+                        };
+                        (name, attr.value)
+                    }));
+                    stack.push((element, namespace));
+                    continue;
+                },
+                XmlEvent::EndElement { .. } => {
+                    let (element, _) = debug_expect!(stack.pop(), Some(element) => element);
+                    if stack.is_empty() {
+                        return Ok(element);
+                    }
+                    Node::Element(element)
+                },
+                XmlEvent::Characters(text) => Node::Text(text),
+                XmlEvent::CData(text) => Node::CData(text),
+                XmlEvent::Comment(text) => Node::Comment(text),
+                XmlEvent::ProcessingInstruction { name, data } => {
+                    Node::ProcessingInstruction { target: name, data }
+                },
+                _ => continue,
+            };
+            if let Some(&mut (ref mut parent, _)) = stack.last_mut() {
+                parent.children.push(node);
+            }
+        }
+        Err(ErrorKind::Custom("no element found".to_string()).into())
+    }
+}
+
+/// The namespaces that an element with the given scope declares, as `xmlns` attributes.
+pub(crate) fn namespace_declarations(
+    scope: &Namespace,
+    parent: Option<&Namespace>,
+) -> Vec<(String, String)> {
+    let mut declarations = Vec::new();
+    for (prefix, uri) in scope {
+        let inherited = parent.and_then(|parent| parent.get(prefix));
+        if prefix == NS_XML_PREFIX || prefix == NS_XMLNS_PREFIX || inherited == Some(uri) {
+            continue;
+        }
+        if prefix.is_empty() {
+            if uri.is_empty() && inherited.is_none() {
+                continue;
+            }
+            declarations.push(("xmlns".to_string(), uri.to_string()));
+        } else {
+            declarations.push((format!("xmlns:{}", prefix), uri.to_string()));
+        }
+    }
+    declarations
+}
+
+impl Serialize for Element {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        self.check().map_err(S::Error::custom)?;
+        serializer.serialize_newtype_struct(MARKUP_NEWTYPE, &self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Element {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Self, D::Error> {
+        let markup = deserializer.deserialize_newtype_struct(MARKUP_NEWTYPE, MarkupVisitor)?;
+        markup.parse().map_err(de::Error::custom)
+    }
+}
+
+/// The content of an element as markup, which is passed through without being interpreted.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde;
+/// # extern crate serde_xml_rs;
+/// # use serde_xml_rs::{from_str, to_string, RawXml};
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// struct Post {
+///     title: String,
+///     body: RawXml,
+/// }
+/// # fn main() {
+/// let s = r##"<Post><title>Hello</title><body><p>Hello, <em>world</em>!</p></body></Post>"##;
+/// let post: Post = from_str(s).unwrap();
+/// assert_eq!(post.body, RawXml("<p>Hello, <em>world</em>!</p>".to_string()));
+/// assert_eq!(to_string(&post).unwrap(), s);
+/// # }
+/// ```
+///
+/// The markup is read back from the events of the parser, so it is equivalent to the original
+/// rather than a copy of it. Character references are expanded, empty elements get an end tag
+/// and every element declares the namespaces it uses. The `Serializer` writes the markup as it
+/// is, without checking it.
+///
+/// As the `$value` of a struct, it holds the rest of the content of the struct, from the first
+/// text or element that isn't another field. Text that was already looked at to get there is
+/// trimmed like any other text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RawXml(pub String);
+
+impl Serialize for RawXml {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(RAW_MARKUP_NEWTYPE, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawXml {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Self, D::Error> {
+        deserializer
+            .deserialize_newtype_struct(RAW_MARKUP_NEWTYPE, MarkupVisitor)
+            .map(RawXml)
+    }
+}
+
+/// Text that the `Serializer` writes as a CDATA section, so that markup in it such as HTML or
+/// scripts is kept readable rather than escaped. It reads back as the same text.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde;
+/// # extern crate serde_xml_rs;
+/// # use serde_xml_rs::{from_str, to_string, CData};
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// struct Widget {
+///     name: String,
+///     script: CData,
+/// }
+/// # fn main() {
+/// let widget = Widget {
+///     name: "clock".to_string(),
+///     script: CData("if (a < b && c) { show(); }".to_string()),
+/// };
+/// let s = to_string(&widget).unwrap();
+/// assert_eq!(
+///     s,
+///     "<Widget><name>clock</name><script><![CDATA[if (a < b && c) { show(); }]]></script></Widget>"
+/// );
+/// assert_eq!(from_str::<Widget>(&s).unwrap(), widget);
+/// # }
+/// ```
+///
+/// Any `]]>` in the text is split across two CDATA sections.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CData(pub String);
+
+impl Serialize for CData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(CDATA_NEWTYPE, &self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for CData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Self, D::Error> {
+        deserializer
+            .deserialize_newtype_struct(CDATA_NEWTYPE, MarkupVisitor)
+            .map(CData)
+    }
+}
+
+/// Writes text as a CDATA section, splitting it where it contains `]]>`.
+pub(crate) fn cdata_section(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// Receives the markup of an element.
+pub(crate) struct MarkupVisitor;
+
+impl<'de> de::Visitor<'de> for MarkupVisitor {
+    type Value = String;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("XML markup")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> ::std::result::Result<String, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> ::std::result::Result<String, E> {
+        Ok(v)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> ::std::result::Result<String, D::Error> {
+        String::deserialize(deserializer)
+    }
+}
+
+/// Lowers a value into an `Element`, as if it was serialized and read back.
+///
+/// ```rust
+/// # #[macro_use]
+/// # extern crate serde_derive;
+/// # extern crate serde;
+/// # extern crate serde_xml_rs;
+/// # use serde_xml_rs::{from_value, to_value};
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// struct Item {
+///     name: String,
+///     source: String,
+/// }
+/// # fn main() {
+/// let item = Item { name: "Banana".to_string(), source: "Store".to_string() };
+/// let mut element = to_value(&item).unwrap();
+/// assert_eq!(element.to_string(), "<Item><name>Banana</name><source>Store</source></Item>");
+///
+/// element.children.remove(1);
+/// element.attributes.push(("source".to_string(), "Market".to_string()));
+/// let item: Item = from_value(element).unwrap();
+/// assert_eq!(item, Item { name: "Banana".to_string(), source: "Market".to_string() });
+/// # }
+/// ```
+pub fn to_value<T: Serialize>(value: &T) -> Result<Element> {
+    to_string(value)?.parse()
+}
+
+/// Builds a value from an `Element`, as if the element was serialized and then deserialized.
+pub fn from_value<T: de::DeserializeOwned>(element: Element) -> Result<T> {
+    from_str(&element.to_string())
+}
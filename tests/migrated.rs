@@ -62,10 +62,10 @@ where
     T: PartialEq + Debug + ser::Serialize + de::Deserialize<'de>,
 {
     for &s in errors {
-        assert!(match from_str::<T>(s) {
-            Err(Error(ErrorKind::Syntax(_), _)) => true,
-            _ => false,
-        });
+        assert!(matches!(
+            from_str::<T>(s),
+            Err(Error(ErrorKind::Syntax(_), _))
+        ));
     }
 }
 
@@ -92,7 +92,6 @@ fn test_namespaces() {
 }
 
 #[test]
-#[ignore] // FIXME
 fn test_doctype() {
     let _ = simple_logger::init();
     #[derive(PartialEq, Serialize, Deserialize, Debug)]
@@ -578,7 +577,7 @@ fn test_hugo_duncan2() {
             struct Helper<U> {
                 item: Vec<U>,
             }
-            let h: Helper<_> = try!(de::Deserialize::deserialize(deserializer));
+            let h: Helper<_> = de::Deserialize::deserialize(deserializer)?;
             Ok(ItemVec(h.item))
         }
     }
@@ -998,12 +997,12 @@ fn futile2() {
     #[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
     struct Object {
         field: Option<Null>,
-    };
+    }
 
     #[derive(Eq, PartialEq, Debug, Serialize, Deserialize)]
     struct Stuff {
         stuff_field: Option<Object>,
-    };
+    }
 
     test_parse_ok(&[
         (
@@ -1,8 +1,13 @@
 #[macro_use]
 extern crate serde_derive;
+extern crate serde;
 extern crate serde_xml_rs;
 
-use serde_xml_rs::{from_str, to_string};
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_xml_rs::{from_reader, from_str, from_value, to_string, to_value, CData, Deserializer, Element, Encoding, RawXml, Serializer};
+use serde_xml_rs::ser::NoneRepresentation;
 
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -13,6 +18,7 @@ struct Item {
 
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 enum Node {
     Boolean(bool),
     Identifier { value: String, index: u32 },
@@ -77,3 +83,728 @@ fn round_trip_list_of_enums() {
     let deserialized_nodes: Nodes = from_str(serialized_nodes.as_str()).unwrap();
     assert_eq!(deserialized_nodes, nodes);
 }
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Shape {
+    #[serde(rename = "circle")]
+    Circle { r: f64 },
+    #[serde(rename = "label")]
+    Label(String),
+    #[serde(rename = "empty")]
+    Empty,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Drawing {
+    main: Shape,
+    caption: Shape,
+    border: Shape,
+}
+
+#[test]
+fn variant_from_attribute() {
+    let drawing = Drawing {
+        main: Shape::Circle { r: 3.0 },
+        caption: Shape::Label("hello".to_string()),
+        border: Shape::Empty,
+    };
+    let should_be = concat!(
+        r#"<Drawing><main type="circle"><r>3</r></main>"#,
+        r#"<caption type="label">hello</caption><border type="empty"></border></Drawing>"#
+    );
+
+    let mut buffer = Vec::new();
+    drawing
+        .serialize(&mut Serializer::new(&mut buffer).variant_attribute("type"))
+        .unwrap();
+    let serialized = String::from_utf8(buffer).unwrap();
+    assert_eq!(serialized, should_be);
+
+    let mut de = Deserializer::new_from_reader(serialized.as_bytes()).variant_attribute("type");
+    assert_eq!(Drawing::deserialize(&mut de).unwrap(), drawing);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Value {
+    #[serde(rename = "xs:int")]
+    Int(i32),
+    #[serde(rename = "xs:string")]
+    Str(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Values {
+    first: Value,
+    second: Value,
+}
+
+#[test]
+fn variant_from_xsi_type() {
+    let src = r#"
+        <values xmlns:i="http://www.w3.org/2001/XMLSchema-instance">
+            <first i:type="xs:int">5</first>
+            <second type="ignored" i:type="xs:string">five</second>
+        </values>
+    "#;
+    let values = Values {
+        first: Value::Int(5),
+        second: Value::Str("five".to_string()),
+    };
+
+    let mut de = Deserializer::new_from_reader(src.as_bytes()).variant_attribute("xsi:type");
+    assert_eq!(Values::deserialize(&mut de).unwrap(), values);
+
+    let mut buffer = Vec::new();
+    values
+        .serialize(&mut Serializer::new(&mut buffer).variant_attribute("xsi:type"))
+        .unwrap();
+    let serialized = String::from_utf8(buffer).unwrap();
+    let mut de = Deserializer::new_from_reader(serialized.as_bytes()).variant_attribute("xsi:type");
+    assert_eq!(Values::deserialize(&mut de).unwrap(), values);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Contact {
+    name: String,
+    phone: Option<String>,
+    age: Option<u32>,
+}
+
+#[test]
+fn none_representations() {
+    let contact = Contact {
+        name: "Joe".to_string(),
+        phone: None,
+        age: None,
+    };
+    let inputs = vec![
+        (
+            NoneRepresentation::Omit,
+            "<Contact><name>Joe</name></Contact>",
+        ),
+        (
+            NoneRepresentation::EmptyElement,
+            "<Contact><name>Joe</name><phone></phone><age></age></Contact>",
+        ),
+        (
+            NoneRepresentation::XsiNil,
+            concat!(
+                r#"<Contact><name>Joe</name>"#,
+                r#"<phone xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:nil="true"></phone>"#,
+                r#"<age xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:nil="true"></age>"#,
+                r#"</Contact>"#
+            ),
+        ),
+    ];
+
+    for (representation, should_be) in inputs {
+        let mut buffer = Vec::new();
+        contact
+            .serialize(&mut Serializer::new(&mut buffer).none_representation(representation))
+            .unwrap();
+        let serialized = String::from_utf8(buffer).unwrap();
+        assert_eq!(serialized, should_be);
+
+        let mut de = Deserializer::new_from_reader(serialized.as_bytes()).empty_as_none(true);
+        assert_eq!(Contact::deserialize(&mut de).unwrap(), contact);
+    }
+}
+
+
+#[test]
+fn output_encodings() {
+    let item = Item {
+        name: "Cr\u{e8}me br\u{fb}l\u{e9}e \u{2013} \u{2603}".to_string(),
+        source: "\u{201c}Caf\u{e9}\u{201d}".to_string(),
+    };
+    let inputs = vec![
+        (
+            Encoding::Latin1,
+            &b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><Item>\
+               <name>Cr\xe8me br\xfbl\xe9e &#x2013; &#x2603;</name>\
+               <source>&#x201C;Caf\xe9&#x201D;</source></Item>"[..],
+        ),
+        (
+            Encoding::Windows1252,
+            &b"<?xml version=\"1.0\" encoding=\"windows-1252\"?><Item>\
+               <name>Cr\xe8me br\xfbl\xe9e \x96 &#x2603;</name>\
+               <source>\x93Caf\xe9\x94</source></Item>"[..],
+        ),
+    ];
+
+    for (encoding, should_be) in inputs {
+        let mut buffer = Vec::new();
+        item.serialize(&mut Serializer::new(&mut buffer).encoding(encoding)).unwrap();
+        assert_eq!(buffer, should_be);
+        assert_eq!(from_reader::<_, Item>(&buffer[..]).unwrap(), item);
+    }
+
+    for &encoding in &[Encoding::Utf16Le, Encoding::Utf16Be] {
+        let mut buffer = Vec::new();
+        item.serialize(&mut Serializer::new(&mut buffer).encoding(encoding)).unwrap();
+        assert!(buffer.starts_with(encoding.bom()));
+        assert_eq!(from_reader::<_, Item>(&buffer[..]).unwrap(), item);
+    }
+
+    let mut buffer = Vec::new();
+    item.serialize(&mut Serializer::new(&mut buffer).encoding(Encoding::Utf8)).unwrap();
+    let serialized = String::from_utf8(buffer).unwrap();
+    assert!(serialized.starts_with(r#"<?xml version="1.0" encoding="UTF-8"?><Item>"#));
+    assert_eq!(from_str::<Item>(&serialized).unwrap(), item);
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Extension {
+    vendor: String,
+    data: Element,
+}
+
+#[test]
+fn element_field() {
+    let s = concat!(
+        r#"<Extension><vendor>ACME &amp; Sons</vendor>"#,
+        r#"<data xmlns:x="urn:acme" x:version="2"><x:flag/><!-- note --><![CDATA[a < b]]></data>"#,
+        r#"</Extension>"#
+    );
+
+    let extension: Extension = from_str(s).unwrap();
+    assert_eq!(extension.vendor, "ACME & Sons");
+    assert_eq!(extension.data.name, "data");
+    assert_eq!(extension.data.attribute("x:version"), Some("2"));
+    assert_eq!(extension.data.text(), "a < b");
+
+    assert_eq!(to_string(&extension).unwrap(), s);
+    assert_eq!(from_value::<Extension>(to_value(&extension).unwrap()).unwrap(), extension);
+}
+
+#[test]
+fn invalid_element_field() {
+    let mut extension = Extension {
+        vendor: "ACME".to_string(),
+        data: Element::new("bad name"),
+    };
+    let error = to_string(&extension).unwrap_err();
+    assert!(error.to_string().contains("invalid XML name: 'bad name'"), "{}", error);
+
+    extension.data = Element::new("data");
+    extension.data.attributes.push(("x y".to_string(), "1".to_string()));
+    assert!(to_string(&extension).unwrap_err().to_string().contains("'x y'"));
+
+    extension.data = Element::new("data");
+    extension.data.children.push(serde_xml_rs::Node::Comment("a--b".to_string()));
+    assert!(to_string(&extension).unwrap_err().to_string().contains("`--`"));
+
+    extension.data = Element::new("data");
+    extension.data.children.push(serde_xml_rs::Node::ProcessingInstruction {
+        target: "app".to_string(),
+        data: Some("a ?> b".to_string()),
+    });
+    assert!(to_string(&extension).unwrap_err().to_string().contains("`?>`"));
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Page {
+    title: String,
+    content: RawXml,
+}
+
+#[test]
+fn raw_xml_field() {
+    let s = concat!(
+        r#"<Page xmlns:h="http://www.w3.org/1999/xhtml"><title>Menu</title><content>"#,
+        r#"<h:p class="intro">Fish &amp; <h:b>Chips</h:b></h:p><!-- prices --><h:br/>"#,
+        r#"</content></Page>"#
+    );
+
+    let page: Page = from_str(s).unwrap();
+    assert_eq!(
+        page.content,
+        RawXml(concat!(
+            r#"<h:p xmlns:h="http://www.w3.org/1999/xhtml" class="intro">Fish &amp; <h:b>Chips</h:b></h:p>"#,
+            r#"<!-- prices --><h:br xmlns:h="http://www.w3.org/1999/xhtml"></h:br>"#
+        ).to_string())
+    );
+
+    let serialized = to_string(&page).unwrap();
+    assert_eq!(
+        serialized,
+        format!("<Page><title>Menu</title><content>{}</content></Page>", page.content.0)
+    );
+    assert_eq!(from_str::<Page>(&serialized).unwrap(), page);
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Change {
+    #[serde(rename = "$name")]
+    kind: String,
+    id: u32,
+}
+
+#[test]
+fn element_name_field() {
+    let change = Change { kind: "Deleted".to_string(), id: 2 };
+
+    let serialized = to_string(&change).unwrap();
+    assert_eq!(serialized, "<Deleted><id>2</id></Deleted>");
+    assert_eq!(from_str::<Change>(&serialized).unwrap(), change);
+}
+
+#[test]
+fn prefixed_element_name_field() {
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Log {
+        #[serde(rename = "$value")]
+        changes: Vec<Change>,
+    }
+
+    let log: Log = from_str(r#"<log xmlns:x="urn:changes"><x:Created id="1"/></log>"#).unwrap();
+    let change = Change { kind: "x:Created".to_string(), id: 1 };
+    assert_eq!(log.changes, vec![change]);
+
+    let serialized = to_string(&log.changes[0]).unwrap();
+    assert_eq!(serialized, "<x:Created><id>1</id></x:Created>");
+    let s = format!(r#"<log xmlns:x="urn:changes">{}</log>"#, serialized);
+    assert_eq!(from_str::<Log>(&s).unwrap(), log);
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Figure {
+    Circle { r: f64 },
+    #[serde(rename = "$other")]
+    Other(String, RawXml),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Layer {
+    Grid { size: u32 },
+    #[serde(rename = "$other")]
+    Other(Element),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Sketch {
+    figure: Figure,
+    layer: Layer,
+}
+
+#[test]
+fn other_variant() {
+    let s = concat!(
+        r#"<Sketch><figure><Polygon><point>1</point>filled</Polygon></figure>"#,
+        r#"<layer><Overlay opacity="0.5"><note>draft</note></Overlay></layer></Sketch>"#
+    );
+
+    let sketch: Sketch = from_str(s).unwrap();
+    assert_eq!(
+        sketch.figure,
+        Figure::Other("Polygon".to_string(), RawXml("<point>1</point>filled".to_string()))
+    );
+    match sketch.layer {
+        Layer::Other(ref overlay) => assert_eq!(overlay.attribute("opacity"), Some("0.5")),
+        ref layer => panic!("unexpected layer: {:?}", layer),
+    }
+
+    assert_eq!(to_string(&sketch).unwrap(), s);
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Config {
+    name: String,
+    setting: BTreeMap<String, u32>,
+}
+
+#[test]
+fn map_key_attribute() {
+    let mut config = Config { name: "server".to_string(), setting: BTreeMap::new() };
+    config.setting.insert("retries".to_string(), 5);
+    config.setting.insert("timeout & delay".to_string(), 30);
+
+    let mut buffer = Vec::new();
+    config.serialize(&mut Serializer::new(&mut buffer).map_key_attribute("key")).unwrap();
+    let serialized = String::from_utf8(buffer).unwrap();
+    assert_eq!(
+        serialized,
+        concat!(
+            r#"<Config><name>server</name><setting key="retries">5</setting>"#,
+            r#"<setting key="timeout &amp; delay">30</setting></Config>"#
+        )
+    );
+
+    let mut de = Deserializer::new_from_reader(serialized.as_bytes()).map_key_attribute("key");
+    assert_eq!(Config::deserialize(&mut de).unwrap(), config);
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+enum Color {
+    Red,
+    Green,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Palette {
+    counts: BTreeMap<Color, u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Levels {
+    level: BTreeMap<u8, String>,
+}
+
+#[test]
+fn typed_map_keys() {
+    let mut palette = Palette { counts: BTreeMap::new() };
+    palette.counts.insert(Color::Red, 3);
+    palette.counts.insert(Color::Green, 5);
+
+    let serialized = to_string(&palette).unwrap();
+    assert_eq!(serialized, "<Palette><counts><Red>3</Red><Green>5</Green></counts></Palette>");
+    assert_eq!(from_str::<Palette>(&serialized).unwrap(), palette);
+
+    let mut levels = Levels { level: BTreeMap::new() };
+    levels.level.insert(1, "low".to_string());
+    levels.level.insert(9, "high".to_string());
+
+    let mut buffer = Vec::new();
+    levels.serialize(&mut Serializer::new(&mut buffer).map_key_attribute("n")).unwrap();
+    let serialized = String::from_utf8(buffer).unwrap();
+    assert_eq!(
+        serialized,
+        r#"<Levels><level n="1">low</level><level n="9">high</level></Levels>"#
+    );
+
+    let mut de = Deserializer::new_from_reader(serialized.as_bytes()).map_key_attribute("n");
+    assert_eq!(Levels::deserialize(&mut de).unwrap(), levels);
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct UserId(u64);
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Account {
+    owner: UserId,
+    label: String,
+}
+
+#[test]
+fn newtype_struct() {
+    let account = Account { owner: UserId(42), label: "main".to_string() };
+
+    let serialized = to_string(&account).unwrap();
+    assert_eq!(serialized, "<Account><owner>42</owner><label>main</label></Account>");
+    assert_eq!(from_str::<Account>(&serialized).unwrap(), account);
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+enum Block {
+    #[serde(rename = "p")]
+    Paragraph(String),
+    #[serde(rename = "code")]
+    Code { lang: String, source: String },
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Article {
+    title: String,
+    tag: Vec<String>,
+    #[serde(rename = "$value")]
+    blocks: Vec<Block>,
+}
+
+#[test]
+fn value_with_named_children() {
+    let article = Article {
+        title: "News".to_string(),
+        tag: vec!["rust".to_string(), "xml".to_string()],
+        blocks: vec![
+            Block::Paragraph("First".to_string()),
+            Block::Code { lang: "rust".to_string(), source: "fn main() {}".to_string() },
+        ],
+    };
+
+    let serialized = to_string(&article).unwrap();
+    assert_eq!(
+        serialized,
+        concat!(
+            "<Article><title>News</title><tag>rust</tag><tag>xml</tag><p>First</p>",
+            "<code><lang>rust</lang><source>fn main() {}</source></code></Article>"
+        )
+    );
+    assert_eq!(from_str::<Article>(&serialized).unwrap(), article);
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Param {
+    name: String,
+    #[serde(default)]
+    required: bool,
+    #[serde(default)]
+    deprecated: bool,
+}
+
+#[test]
+fn bool_as_presence() {
+    let param = Param { name: "id".to_string(), required: true, deprecated: false };
+
+    let mut buffer = Vec::new();
+    param.serialize(&mut Serializer::new(&mut buffer).bool_as_presence(true)).unwrap();
+    let serialized = String::from_utf8(buffer).unwrap();
+    assert_eq!(serialized, "<Param><name>id</name><required/></Param>");
+
+    let mut de = Deserializer::new_from_reader(serialized.as_bytes()).bool_as_presence(true);
+    assert_eq!(Param::deserialize(&mut de).unwrap(), param);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Switches {
+    v: Vec<bool>,
+    default: Option<bool>,
+    fallback: Option<bool>,
+    unset: Option<bool>,
+}
+
+#[test]
+fn bool_as_presence_outside_fields() {
+    let switches = Switches {
+        v: vec![true, false, true],
+        default: Some(false),
+        fallback: Some(true),
+        unset: None,
+    };
+
+    let mut buffer = Vec::new();
+    switches.serialize(&mut Serializer::new(&mut buffer).bool_as_presence(true)).unwrap();
+    let serialized = String::from_utf8(buffer).unwrap();
+    assert_eq!(
+        serialized,
+        concat!(
+            "<Switches><v>true</v><v>false</v><v>true</v>",
+            "<default>false</default><fallback>true</fallback><unset></unset></Switches>"
+        )
+    );
+
+    let mut de = Deserializer::new_from_reader(serialized.as_bytes())
+        .bool_as_presence(true)
+        .empty_as_none(true);
+    assert_eq!(Switches::deserialize(&mut de).unwrap(), switches);
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct License {
+    #[serde(rename = "$comments")]
+    comments: Vec<String>,
+    #[serde(rename = "$processing_instructions")]
+    processing_instructions: Vec<String>,
+    holder: String,
+}
+
+#[test]
+fn comments_and_processing_instructions() {
+    let license = License {
+        comments: vec![" SPDX-License-Identifier: MIT ".to_string()],
+        processing_instructions: vec!["app-version 3.2".to_string()],
+        holder: "ACME".to_string(),
+    };
+
+    let serialized = to_string(&license).unwrap();
+    assert_eq!(
+        serialized,
+        concat!(
+            "<License><!-- SPDX-License-Identifier: MIT --><?app-version 3.2?>",
+            "<holder>ACME</holder></License>"
+        )
+    );
+
+    let mut de = Deserializer::new_from_reader(serialized.as_bytes()).capture_comments(true);
+    assert_eq!(License::deserialize(&mut de).unwrap(), license);
+}
+
+
+#[test]
+fn escaped_text() {
+    let item = Item {
+        name: "<b>Fish & Chips</b>".to_string(),
+        source: "a ]]> b".to_string(),
+    };
+
+    let serialized = to_string(&item).unwrap();
+    assert_eq!(
+        serialized,
+        "<Item><name>&lt;b&gt;Fish &amp; Chips&lt;/b&gt;</name><source>a ]]&gt; b</source></Item>"
+    );
+    assert_eq!(from_str::<Item>(&serialized).unwrap(), item);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Widget {
+    name: String,
+    script: CData,
+    template: CData,
+}
+
+#[test]
+fn cdata() {
+    let widget = Widget {
+        name: "clock".to_string(),
+        script: CData("  if (a < b && c) { show(\"]]>\"); }\n".to_string()),
+        template: CData(String::new()),
+    };
+
+    let serialized = to_string(&widget).unwrap();
+    assert_eq!(
+        serialized,
+        concat!(
+            "<Widget><name>clock</name>",
+            "<script><![CDATA[  if (a < b && c) { show(\"]]]]><![CDATA[>\"); }\n]]></script>",
+            "<template><![CDATA[]]></template></Widget>"
+        )
+    );
+    assert_eq!(from_str::<Widget>(&serialized).unwrap(), widget);
+    assert_eq!(from_value::<Widget>(to_value(&widget).unwrap()).unwrap(), widget);
+}
+
+#[test]
+fn written_comments_and_processing_instructions() {
+    let license = License {
+        comments: vec![],
+        processing_instructions: vec![],
+        holder: "ACME".to_string(),
+    };
+    let mut buffer = Vec::new();
+    {
+        let mut ser = Serializer::new(&mut buffer);
+        ser.write_processing_instruction("xml-stylesheet", Some("href=\"license.xsl\""))
+            .unwrap();
+        ser.write_comment(" generated, do not edit ").unwrap();
+        license.serialize(&mut ser).unwrap();
+    }
+    let serialized = String::from_utf8(buffer).unwrap();
+    assert_eq!(
+        serialized,
+        concat!(
+            "<?xml-stylesheet href=\"license.xsl\"?><!-- generated, do not edit -->",
+            "<License><holder>ACME</holder></License>"
+        )
+    );
+
+    let mut de = Deserializer::new_from_reader(serialized.as_bytes()).capture_comments(true);
+    let license = License::deserialize(&mut de).unwrap();
+    assert_eq!(license.comments, vec![" generated, do not edit ".to_string()]);
+    assert_eq!(
+        license.processing_instructions,
+        vec!["xml-stylesheet href=\"license.xsl\"".to_string()]
+    );
+
+    let mut ser = Serializer::new(Vec::new());
+    assert!(ser.write_comment("a -- b").is_err());
+    assert!(ser.write_comment("trailing -").is_err());
+    assert!(ser.write_processing_instruction("app", Some("a ?> b")).is_err());
+    assert!(ser.write_processing_instruction("xml", None).is_err());
+    assert!(ser.write_processing_instruction("two words", None).is_err());
+
+    let license = License {
+        comments: vec!["a -- b".to_string()],
+        processing_instructions: vec![],
+        holder: "ACME".to_string(),
+    };
+    assert!(to_string(&license).is_err());
+}
+
+
+#[test]
+fn cdata_in_output_encodings() {
+    let widget = Widget {
+        name: "price".to_string(),
+        script: CData("cost: \u{20ac}5 ]]> \u{e9}".to_string()),
+        template: CData(String::new()),
+    };
+
+    let mut buffer = Vec::new();
+    widget.serialize(&mut Serializer::new(&mut buffer).encoding(Encoding::Latin1)).unwrap();
+    assert_eq!(
+        buffer,
+        &b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><Widget><name>price</name>\
+           <script><![CDATA[cost: ]]>&#x20AC;<![CDATA[5 ]]]]><![CDATA[> \xe9]]></script>\
+           <template><![CDATA[]]></template></Widget>"[..]
+    );
+    assert_eq!(from_reader::<_, Widget>(&buffer[..]).unwrap(), widget);
+
+    let mut ser = Serializer::new(Vec::new()).encoding(Encoding::Latin1);
+    assert!(ser.write_comment(" \u{20ac} ").is_err());
+    let mut ser = Serializer::new(Vec::new()).encoding(Encoding::Latin1);
+    assert!(ser.start_element("\u{3a9}").is_err());
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Row {
+    id: u32,
+    label: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Export {
+    #[serde(rename = "Row")]
+    rows: Vec<Row>,
+}
+
+#[test]
+fn streamed_elements() {
+    let mut buffer = Vec::new();
+    {
+        let mut ser = Serializer::new(&mut buffer);
+        ser.start_element("Export").unwrap();
+        for id in 0..3 {
+            let row = Row { id, label: format!("row {}", id) };
+            row.serialize(&mut ser).unwrap();
+            ser.flush().unwrap();
+        }
+        ser.end_element().unwrap();
+        assert!(ser.end_element().is_err());
+        assert!(ser.start_element("not a name").is_err());
+    }
+
+    let export = from_reader::<_, Export>(buffer.as_slice()).unwrap();
+    assert_eq!(export.rows.len(), 3);
+    assert_eq!(export.rows[2], Row { id: 2, label: "row 2".to_string() });
+}
+
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Dimensions {
+    width: u32,
+    height: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Frame {
+    title: String,
+    size: Dimensions,
+}
+
+#[test]
+fn nested_structs() {
+    let frame = Frame {
+        title: "main".to_string(),
+        size: Dimensions { width: 640, height: 480 },
+    };
+
+    // A struct held by a field is wrapped in an element named after the struct.
+    assert_eq!(
+        to_string(&frame).unwrap(),
+        concat!(
+            "<Frame><title>main</title>",
+            "<size><Dimensions><width>640</width><height>480</height></Dimensions></size></Frame>"
+        )
+    );
+}
@@ -1,11 +1,16 @@
 #[macro_use]
 extern crate serde_derive;
+extern crate serde;
 extern crate serde_xml_rs;
 
 extern crate log;
 extern crate simple_logger;
 
-use serde_xml_rs::from_str;
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_xml_rs::{from_reader, from_str, Deserializer, Element, Encoding, ErrorKind, EventReader, Node, RawXml};
+use serde_xml_rs::de::{EntityPolicy, Limits};
 
 #[derive(Debug, Deserialize, PartialEq)]
 struct Item {
@@ -41,7 +46,9 @@ fn multiple_roots_attributes() {
         <item name="hello" source="world.rs" />
     "##;
 
-    let item: Vec<Item> = from_str(s).unwrap();
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).fragment(true);
+    let item = Vec::<Item>::deserialize(&mut de).unwrap();
+    de.end().unwrap();
 
     assert_eq!(
         item,
@@ -159,3 +166,1094 @@ fn collection_of_enums() {
         }
     );
 }
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Stats {
+    count: u32,
+    ratio: f64,
+    enabled: bool,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Report {
+    name: String,
+    #[serde(flatten)]
+    stats: Stats,
+}
+
+#[test]
+fn flattened_struct_from_children() {
+    let _ = simple_logger::init();
+
+    let s = r##"
+        <report>
+            <name>daily</name>
+            <count>3</count>
+            <ratio>0.5</ratio>
+            <enabled>true</enabled>
+        </report>
+    "##;
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).infer_scalars(true);
+    let report = Report::deserialize(&mut de).unwrap();
+
+    assert_eq!(
+        report,
+        Report {
+            name: "daily".to_string(),
+            stats: Stats {
+                count: 3,
+                ratio: 0.5,
+                enabled: true,
+            },
+        }
+    );
+}
+
+#[test]
+fn flattened_struct_from_attributes() {
+    let _ = simple_logger::init();
+
+    let s = r##"
+        <report name="daily" count="3" ratio="2" enabled="false" />
+    "##;
+
+    // Without infer_scalars the buffered text stays a string, which serde can't read as a number.
+    let error = from_str::<Report>(s).unwrap_err();
+    assert!(error.to_string().contains("invalid type: string"), "{}", error);
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).infer_scalars(true);
+    let report = Report::deserialize(&mut de).unwrap();
+
+    assert_eq!(
+        report,
+        Report {
+            name: "daily".to_string(),
+            stats: Stats {
+                count: 3,
+                ratio: 2.0,
+                enabled: false,
+            },
+        }
+    );
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+enum Shape {
+    Circle { r: f64 },
+    Rect { w: u32, h: u32, label: String },
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Drawing {
+    #[serde(rename = "shape", default)]
+    shapes: Vec<Shape>,
+}
+
+#[test]
+fn internally_tagged_enum() {
+    let _ = simple_logger::init();
+
+    let s = r##"
+        <drawing>
+            <shape type="Circle" r="1.5" />
+            <shape>
+                <type>Rect</type>
+                <w>2</w>
+                <h>3</h>
+                <label>door</label>
+            </shape>
+        </drawing>
+    "##;
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).infer_scalars(true);
+    let drawing = Drawing::deserialize(&mut de).unwrap();
+
+    assert_eq!(
+        drawing,
+        Drawing {
+            shapes: vec![
+                Shape::Circle { r: 1.5 },
+                Shape::Rect {
+                    w: 2,
+                    h: 3,
+                    label: "door".to_string(),
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn internally_tagged_enum_root() {
+    let _ = simple_logger::init();
+
+    let s = r##"<Shape type="Rect" w="4" h="5"><label>window</label></Shape>"##;
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).infer_scalars(true);
+    let shape = Shape::deserialize(&mut de).unwrap();
+
+    assert_eq!(
+        shape,
+        Shape::Rect {
+            w: 4,
+            h: 5,
+            label: "window".to_string(),
+        }
+    );
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Address {
+    zip: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Outer {
+    name: String,
+    #[serde(flatten)]
+    address: Address,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+enum Message {
+    Ping { id: String },
+}
+
+#[test]
+fn buffered_numeric_strings() {
+    let _ = simple_logger::init();
+
+    let outer: Outer = from_str(r#"<outer name="x" zip="12345"/>"#).unwrap();
+    assert_eq!(
+        outer,
+        Outer {
+            name: "x".to_string(),
+            address: Address { zip: "12345".to_string() },
+        }
+    );
+
+    let message: Message = from_str(r#"<msg type="Ping" id="42"/>"#).unwrap();
+    assert_eq!(message, Message::Ping { id: "42".to_string() });
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+enum Body {
+    Number(u32),
+    Text(String),
+    Link { href: String, title: Option<String> },
+    List { entry: Vec<String> },
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Page {
+    #[serde(rename = "body")]
+    bodies: Vec<Body>,
+}
+
+#[test]
+fn untagged_enum() {
+    let _ = simple_logger::init();
+
+    let s = r##"
+        <page>
+            <body>5</body>
+            <body>hello</body>
+            <body href="/home"><title>Home</title></body>
+            <body href="/about" />
+            <body>
+                <entry>a</entry>
+                <entry>b</entry>
+            </body>
+        </page>
+    "##;
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).infer_scalars(true);
+    let page = Page::deserialize(&mut de).unwrap();
+
+    assert_eq!(
+        page,
+        Page {
+            bodies: vec![
+                Body::Number(5),
+                Body::Text("hello".to_string()),
+                Body::Link {
+                    href: "/home".to_string(),
+                    title: Some("Home".to_string()),
+                },
+                Body::Link {
+                    href: "/about".to_string(),
+                    title: None,
+                },
+                Body::List {
+                    entry: vec!["a".to_string(), "b".to_string()],
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn untagged_enum_root() {
+    let _ = simple_logger::init();
+
+    let body: Body = from_str("<body>hello</body>").unwrap();
+    assert_eq!(body, Body::Text("hello".to_string()));
+
+    let body: Body = from_str(r#"<body href="/home" />"#).unwrap();
+    assert_eq!(
+        body,
+        Body::Link {
+            href: "/home".to_string(),
+            title: None,
+        }
+    );
+}
+
+#[test]
+fn untagged_enum_numeric_text() {
+    let _ = simple_logger::init();
+
+    let body: Body = from_str("<body>42</body>").unwrap();
+    assert_eq!(body, Body::Text("42".to_string()));
+
+    let body: Body = from_str(r#"<body href="7"><title>2.5</title></body>"#).unwrap();
+    assert_eq!(
+        body,
+        Body::Link {
+            href: "7".to_string(),
+            title: Some("2.5".to_string()),
+        }
+    );
+
+    let body: Body = from_str("<body><entry>1</entry><entry>true</entry></body>").unwrap();
+    assert_eq!(
+        body,
+        Body::List {
+            entry: vec!["1".to_string(), "true".to_string()],
+        }
+    );
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Contact {
+    name: Option<String>,
+    phone: Option<String>,
+    age: Option<u32>,
+}
+
+#[test]
+fn option_from_xsi_nil() {
+    let _ = simple_logger::init();
+
+    let s = r##"
+        <contact xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+            <name></name>
+            <phone xsi:nil="true" />
+            <age xsi:nil="true"></age>
+        </contact>
+    "##;
+
+    let contact: Contact = from_str(s).unwrap();
+
+    assert_eq!(
+        contact,
+        Contact {
+            name: Some("".to_string()),
+            phone: None,
+            age: None,
+        }
+    );
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Tree {
+    label: String,
+    #[serde(default)]
+    tree: Vec<Tree>,
+}
+
+fn read_tree(s: &str, limits: Limits) -> Result<Tree, serde_xml_rs::Error> {
+    Tree::deserialize(&mut Deserializer::new_from_reader(s.as_bytes()).limits(limits))
+}
+
+fn assert_limit_exceeded(result: Result<Tree, serde_xml_rs::Error>, limit: &str) {
+    match result {
+        Err(e) => match *e.kind() {
+            ErrorKind::LimitExceeded(ref exceeded, _) => assert_eq!(exceeded, limit),
+            _ => panic!("unexpected error: {}", e),
+        },
+        Ok(tree) => panic!("unexpected tree: {:?}", tree),
+    }
+}
+
+#[test]
+fn limits() {
+    let _ = simple_logger::init();
+
+    let s = r##"
+        <tree label="root">
+            <tree label="child">
+                <tree label="grandchild" />
+            </tree>
+        </tree>
+    "##;
+
+    assert!(read_tree(s, Limits::default()).is_ok());
+    assert!(
+        read_tree(
+            s,
+            Limits {
+                max_depth: Some(3),
+                max_attributes: Some(1),
+                max_text_length: Some(10),
+                max_events: Some(11),
+                max_input_size: Some(s.len() as u64),
+            }
+        ).is_ok()
+    );
+
+    assert_limit_exceeded(
+        read_tree(s, Limits { max_depth: Some(2), ..Limits::default() }),
+        "nesting depth",
+    );
+    assert_limit_exceeded(
+        read_tree(s, Limits { max_attributes: Some(0), ..Limits::default() }),
+        "number of attributes",
+    );
+    assert_limit_exceeded(
+        read_tree(s, Limits { max_text_length: Some(5), ..Limits::default() }),
+        "attribute length",
+    );
+    assert_limit_exceeded(
+        read_tree(s, Limits { max_events: Some(5), ..Limits::default() }),
+        "number of events",
+    );
+    assert_limit_exceeded(
+        read_tree(s, Limits { max_input_size: Some(20), ..Limits::default() }),
+        "input size",
+    );
+    assert_limit_exceeded(
+        read_tree(
+            "<tree><label>a rather long label</label></tree>",
+            Limits { max_text_length: Some(10), ..Limits::default() },
+        ),
+        "text length",
+    );
+    assert_limit_exceeded(
+        read_tree(
+            "<tree><label><![CDATA[abcdefgh]]><![CDATA[ijklmnop]]></label></tree>",
+            Limits { max_text_length: Some(10), ..Limits::default() },
+        ),
+        "text length",
+    );
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Note {
+    author: String,
+}
+
+fn read_note(s: &str, policy: EntityPolicy) -> Result<Note, serde_xml_rs::Error> {
+    Note::deserialize(&mut Deserializer::new_from_reader(s.as_bytes()).entity_policy(policy))
+}
+
+#[test]
+fn entity_policies() {
+    let _ = simple_logger::init();
+
+    let internal = EntityPolicy::Internal {
+        max_expansion_length: 1000,
+        max_expansion_depth: 4,
+    };
+    let note = Note {
+        author: "Jane Doe".to_string(),
+    };
+
+    let plain = r##"<!DOCTYPE note><note><author>Jane Doe</author></note>"##;
+    assert_eq!(read_note(plain, EntityPolicy::default()).unwrap(), note);
+    assert_eq!(read_note(plain, internal).unwrap(), note);
+    match read_note(plain, EntityPolicy::RejectDtd).unwrap_err().kind() {
+        &ErrorKind::EntityPolicy(_) => {},
+        e => panic!("unexpected error: {}", e),
+    }
+
+    let system = r##"<!DOCTYPE note SYSTEM "note[1].dtd"><note><author>Jane Doe</author></note>"##;
+    assert_eq!(read_note(system, EntityPolicy::default()).unwrap(), note);
+
+    let declared = r##"
+        <!DOCTYPE note [ <!ENTITY name "Jane Doe"> ]>
+        <note><author>&name;</author></note>
+    "##;
+    assert_eq!(read_note(declared, internal).unwrap(), note);
+    match read_note(declared, EntityPolicy::PredefinedOnly).unwrap_err().kind() {
+        &ErrorKind::EntityPolicy(_) => {},
+        e => panic!("unexpected error: {}", e),
+    }
+
+    let in_attribute = r##"<!DOCTYPE note [ <!ENTITY name "Jane Doe"> ]><note author="&name;"/>"##;
+    assert_eq!(read_note(in_attribute, internal).unwrap(), note);
+    match read_note(in_attribute, EntityPolicy::PredefinedOnly).unwrap_err().kind() {
+        &ErrorKind::EntityPolicy(_) => {},
+        e => panic!("unexpected error: {}", e),
+    }
+
+    let external = r##"
+        <!DOCTYPE note [ <!ENTITY name SYSTEM "file:///etc/passwd"> ]>
+        <note><author>&name;</author></note>
+    "##;
+    match read_note(external, internal).unwrap_err().kind() {
+        &ErrorKind::EntityPolicy(_) => {},
+        e => panic!("unexpected error: {}", e),
+    }
+
+    let laughs = r##"
+        <!DOCTYPE note [
+            <!ENTITY lol "lol">
+            <!ENTITY lol1 "&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;&lol;">
+            <!ENTITY lol2 "&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;&lol1;">
+            <!ENTITY lol3 "&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;&lol2;">
+            <!ENTITY lol4 "&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;&lol3;">
+        ]>
+        <note><author>&lol4;</author></note>
+    "##;
+    match read_note(laughs, internal).unwrap_err().kind() {
+        &ErrorKind::LimitExceeded(ref limit, 1000) => assert_eq!(limit, "entity expansion"),
+        e => panic!("unexpected error: {}", e),
+    }
+
+    // The declarations are measured before the parser expands any of them.
+    let tight = EntityPolicy::Internal {
+        max_expansion_length: 4,
+        max_expansion_depth: 4,
+    };
+    let unused = r##"
+        <!DOCTYPE note [ <!ENTITY name "Jane Doe"> ]>
+        <note><author>Jane Doe</author></note>
+    "##;
+    match read_note(unused, tight).unwrap_err().kind() {
+        &ErrorKind::LimitExceeded(ref limit, 4) => assert_eq!(limit, "entity expansion"),
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Article {
+    title: String,
+    body: String,
+}
+
+#[test]
+fn html5_entities() {
+    let _ = simple_logger::init();
+
+    let s = r##"
+        <article>
+            <title>Caf&eacute;&nbsp;&amp;&nbsp;Bar</title>
+            <body>&copy; 2018 &mdash; &custom;</body>
+        </article>
+    "##;
+
+    assert!(Article::deserialize(&mut Deserializer::new_from_reader(s.as_bytes())).is_err());
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes())
+        .html5_entities()
+        .entities(vec![("custom", "made with <3")]);
+    let article = Article::deserialize(&mut de).unwrap();
+
+    assert_eq!(
+        article,
+        Article {
+            title: "Caf\u{e9}\u{a0}&\u{a0}Bar".to_string(),
+            body: "\u{a9} 2018 \u{2014} made with <3".to_string(),
+        }
+    );
+
+    // An `EventReader` that was passed in keeps its own entities.
+    let mut de = Deserializer::new(EventReader::new(s.as_bytes())).html5_entities();
+    match *Article::deserialize(&mut de).unwrap_err().kind() {
+        ErrorKind::UnsupportedOperation(ref option) => assert_eq!(option, "entities"),
+        ref e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct Transfer {
+    payee: String,
+    amount: String,
+}
+
+fn utf16(s: &str, bom: bool, little_endian: bool) -> Vec<u8> {
+    let mut units = s.encode_utf16().collect::<Vec<_>>();
+    if bom {
+        units.insert(0, 0xfeff);
+    }
+    units
+        .into_iter()
+        .flat_map(|unit| if little_endian { unit.to_le_bytes() } else { unit.to_be_bytes() })
+        .collect()
+}
+
+#[test]
+fn input_encodings() {
+    let _ = simple_logger::init();
+
+    let transfer = Transfer {
+        payee: "Ren\u{e9}e M\u{fc}ller".to_string(),
+        amount: "\u{20ac}1,000".to_string(),
+    };
+
+    let latin1 = b"<?xml version='1.0' encoding='iso-8859-1'?>
+        <transfer><payee>Ren\xe9e M\xfcller</payee><amount>\x801,000</amount></transfer>";
+    let decoded: Transfer = from_reader(&latin1[..]).unwrap();
+    assert_eq!(decoded.payee, transfer.payee);
+    assert_eq!(decoded.amount, "\u{80}1,000");
+
+    let windows1252 = b"<?xml version=\"1.0\" encoding=\"windows-1252\"?>
+        <transfer><payee>Ren\xe9e M\xfcller</payee><amount>\x801,000</amount></transfer>";
+    assert_eq!(from_reader::<_, Transfer>(&windows1252[..]).unwrap(), transfer);
+
+    let mut de = Deserializer::new_from_reader(&latin1[..]).encoding(Encoding::Windows1252);
+    assert_eq!(Transfer::deserialize(&mut de).unwrap(), transfer);
+
+    let s = "<?xml version=\"1.0\" encoding=\"UTF-16\"?>\r\n\
+             <transfer><payee>Ren\u{e9}e M\u{fc}ller</payee><amount>\u{20ac}1,000</amount></transfer>";
+    for &(bom, little_endian) in &[(true, true), (true, false), (false, true), (false, false)] {
+        let bytes = utf16(s, bom, little_endian);
+        assert_eq!(from_reader::<_, Transfer>(&bytes[..]).unwrap(), transfer);
+    }
+
+    let mut bytes = b"\xef\xbb\xbf".to_vec();
+    bytes.extend_from_slice(s.replace("UTF-16", "UTF-8").as_bytes());
+    assert_eq!(from_reader::<_, Transfer>(&bytes[..]).unwrap(), transfer);
+
+    let ebcdic = b"<?xml version=\"1.0\" encoding=\"EBCDIC\"?><transfer />";
+    match from_reader::<_, Transfer>(&ebcdic[..]).unwrap_err().kind() {
+        ErrorKind::UnsupportedEncoding(encoding) => assert_eq!(encoding, "EBCDIC"),
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn element_tree() {
+    let _ = simple_logger::init();
+
+    let s = r##"
+        <?xml version="1.0" encoding="UTF-8"?>
+        <!-- envelope -->
+        <soap:Envelope xmlns:soap="http://www.w3.org/2003/05/soap-envelope">
+            <soap:Body>
+                <m:GetPrice xmlns:m="https://www.example.org/stock" currency="EUR">
+                    <m:Item>Apple &amp; Pear</m:Item>
+                    <!-- check the price -->
+                    <m:Note><![CDATA[<b>fresh</b>]]></m:Note>
+                </m:GetPrice>
+            </soap:Body>
+        </soap:Envelope>
+    "##;
+
+    let envelope: Element = from_str(s).unwrap();
+
+    let mut item = Element::new("m:Item");
+    item.namespace = Some("https://www.example.org/stock".to_string());
+    item.children.push(Node::Text("Apple & Pear".to_string()));
+    let mut note = Element::new("m:Note");
+    note.namespace = Some("https://www.example.org/stock".to_string());
+    note.children.push(Node::CData("<b>fresh</b>".to_string()));
+    let mut get_price = Element::new("m:GetPrice");
+    get_price.namespace = Some("https://www.example.org/stock".to_string());
+    get_price.attributes = vec![
+        ("xmlns:m".to_string(), "https://www.example.org/stock".to_string()),
+        ("currency".to_string(), "EUR".to_string()),
+    ];
+    get_price.children = vec![
+        Node::Element(item),
+        Node::Comment(" check the price ".to_string()),
+        Node::Element(note),
+    ];
+    let mut body = Element::new("soap:Body");
+    body.namespace = Some("http://www.w3.org/2003/05/soap-envelope".to_string());
+    body.children.push(Node::Element(get_price));
+
+    assert_eq!(envelope.name, "soap:Envelope");
+    assert_eq!(envelope.namespace.as_deref(), Some("http://www.w3.org/2003/05/soap-envelope"));
+    assert_eq!(
+        envelope.attributes,
+        vec![("xmlns:soap".to_string(), "http://www.w3.org/2003/05/soap-envelope".to_string())]
+    );
+    assert_eq!(envelope.children, vec![Node::Element(body)]);
+}
+
+#[test]
+fn element_name_field() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Change {
+        #[serde(rename = "$name")]
+        kind: String,
+        id: u32,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Log {
+        #[serde(rename = "$value")]
+        changes: Vec<Change>,
+    }
+
+    let s = r##"
+        <log>
+            <Created id="1"/>
+            <Updated id="1"><note>typo</note></Updated>
+            <Deleted id="2"/>
+        </log>
+    "##;
+
+    let log: Log = from_str(s).unwrap();
+    let kinds: Vec<&str> = log.changes.iter().map(|change| change.kind.as_str()).collect();
+    assert_eq!(kinds, vec!["Created", "Updated", "Deleted"]);
+    assert_eq!(log.changes[2], Change { kind: "Deleted".to_string(), id: 2 });
+}
+
+#[test]
+fn raw_xml_value_with_mixed_content() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Post {
+        title: String,
+        #[serde(rename = "$value")]
+        body: RawXml,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Feed {
+        #[serde(rename = "Post")]
+        posts: Vec<Post>,
+    }
+
+    let s = r##"
+        <Feed>
+            <Post><title>first</title>text <b>x</b></Post>
+            <Post><title>second</title><b>y</b> and <i>z</i></Post>
+        </Feed>
+    "##;
+
+    let feed: Feed = from_str(s).unwrap();
+    assert_eq!(
+        feed.posts,
+        vec![
+            Post { title: "first".to_string(), body: RawXml("text<b>x</b>".to_string()) },
+            Post {
+                title: "second".to_string(),
+                body: RawXml("<b>y</b> and <i>z</i>".to_string()),
+            },
+        ]
+    );
+}
+
+#[test]
+fn other_variant() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Shape {
+        Circle { r: f64 },
+        #[serde(rename = "$other")]
+        Other(String, RawXml),
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Layer {
+        Grid,
+        #[serde(rename = "$other")]
+        Other(Element),
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Drawing {
+        #[serde(rename = "$value")]
+        shapes: Vec<Shape>,
+    }
+
+    let s = r##"
+        <Drawing>
+            <Circle r="3"/>
+            <Polygon sides="5"><point x="0" y="0"/>fill</Polygon>
+            <Circle r="4"/>
+        </Drawing>
+    "##;
+
+    let drawing: Drawing = from_str(s).unwrap();
+    assert_eq!(
+        drawing.shapes,
+        vec![
+            Shape::Circle { r: 3.0 },
+            Shape::Other(
+                "Polygon".to_string(),
+                RawXml(r#"<point x="0" y="0"></point>fill"#.to_string())
+            ),
+            Shape::Circle { r: 4.0 },
+        ]
+    );
+
+    let layer: Layer = from_str(r#"<Sketch pen="2">draft</Sketch>"#).unwrap();
+    let mut sketch = Element::new("Sketch");
+    sketch.attributes.push(("pen".to_string(), "2".to_string()));
+    sketch.children.push(Node::Text("draft".to_string()));
+    assert_eq!(layer, Layer::Other(sketch));
+    assert_eq!(from_str::<Layer>("<Grid/>").unwrap(), Layer::Grid);
+}
+
+#[test]
+fn map_key_attribute() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Port {
+        protocol: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        setting: HashMap<String, u32>,
+        port: HashMap<u16, Port>,
+        user: Vec<String>,
+    }
+
+    let s = r##"
+        <config>
+            <setting key="timeout">30</setting>
+            <setting key="retries">5</setting>
+            <port key="80" protocol="http"/>
+            <port key="443"><protocol>https</protocol></port>
+            <user>alice</user>
+        </config>
+    "##;
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).map_key_attribute("key");
+    let config = Config::deserialize(&mut de).unwrap();
+    assert_eq!(config.setting.len(), 2);
+    assert_eq!(config.setting["timeout"], 30);
+    assert_eq!(config.setting["retries"], 5);
+    assert_eq!(config.port[&80], Port { protocol: "http".to_string() });
+    assert_eq!(config.port[&443], Port { protocol: "https".to_string() });
+    assert_eq!(config.user, vec!["alice"]);
+
+    let s = r##"<config><setting key="timeout">30</setting><setting>5</setting></config>"##;
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).map_key_attribute("key");
+    #[derive(Debug, Deserialize)]
+    struct Settings {
+        #[allow(dead_code)]
+        setting: HashMap<String, u32>,
+    }
+    match Settings::deserialize(&mut de).unwrap_err().kind() {
+        ErrorKind::Custom(message) => assert_eq!(message, "missing the `key` attribute"),
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn typed_map_keys() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq, Eq, Hash)]
+    enum Color {
+        Red,
+        Green,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Palette {
+        counts: HashMap<Color, u32>,
+        flag: HashMap<bool, String>,
+    }
+
+    let s = r##"
+        <palette>
+            <counts><Red>3</Red><Green>5</Green></counts>
+            <flag key="true">on</flag>
+            <flag key="0">off</flag>
+        </palette>
+    "##;
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).map_key_attribute("key");
+    let palette = Palette::deserialize(&mut de).unwrap();
+    assert_eq!(palette.counts[&Color::Red], 3);
+    assert_eq!(palette.counts[&Color::Green], 5);
+    assert_eq!(palette.flag[&true], "on");
+    assert_eq!(palette.flag[&false], "off");
+
+    let s = r##"<ports><port key="http">web</port></ports>"##;
+    #[derive(Debug, Deserialize)]
+    struct Ports {
+        #[allow(dead_code)]
+        port: HashMap<u16, String>,
+    }
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).map_key_attribute("key");
+    assert!(Ports::deserialize(&mut de).is_err());
+}
+
+#[test]
+fn newtype_structs() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct UserId(u64);
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Price(f64);
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Amount {
+        currency: String,
+        #[serde(rename = "$value")]
+        value: Price,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Order {
+        id: UserId,
+        owner: UserId,
+        price: Price,
+        total: Amount,
+        note: Option<UserId>,
+    }
+
+    let s = r##"
+        <order id="7">
+            <owner>42</owner>
+            <price>9.5</price>
+            <total currency="EUR">19</total>
+        </order>
+    "##;
+
+    let order: Order = from_str(s).unwrap();
+    assert_eq!(
+        order,
+        Order {
+            id: UserId(7),
+            owner: UserId(42),
+            price: Price(9.5),
+            total: Amount { currency: "EUR".to_string(), value: Price(19.0) },
+            note: None,
+        }
+    );
+    assert!(from_str::<Order>(r#"<order id="x"><owner>1</owner></order>"#).is_err());
+}
+
+#[test]
+fn value_with_named_children() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Block {
+        #[serde(rename = "p")]
+        Paragraph(String),
+        #[serde(rename = "img")]
+        Image { src: String },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Article {
+        lang: String,
+        title: String,
+        #[serde(rename = "$value")]
+        blocks: Vec<Block>,
+        footer: String,
+    }
+
+    let s = r##"
+        <article lang="en">
+            <title>News</title>
+            <p>First</p>
+            <img src="a.png"/>
+            <p>Second</p>
+            <footer>End</footer>
+        </article>
+    "##;
+
+    let article: Article = from_str(s).unwrap();
+    assert_eq!(
+        article,
+        Article {
+            lang: "en".to_string(),
+            title: "News".to_string(),
+            blocks: vec![
+                Block::Paragraph("First".to_string()),
+                Block::Image { src: "a.png".to_string() },
+                Block::Paragraph("Second".to_string()),
+            ],
+            footer: "End".to_string(),
+        }
+    );
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Label {
+        code: u32,
+        #[serde(rename = "$value")]
+        text: String,
+    }
+
+    let label: Label = from_str("<label>Hello<code>4</code></label>").unwrap();
+    assert_eq!(label, Label { code: 4, text: "Hello".to_string() });
+}
+
+#[test]
+fn bool_as_presence() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Column {
+        #[serde(default)]
+        required: bool,
+        #[serde(default)]
+        unique: bool,
+        indexed: bool,
+        hidden: bool,
+    }
+
+    let s = r##"
+        <column>
+            <required/>
+            <indexed></indexed>
+            <hidden>false</hidden>
+        </column>
+    "##;
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).bool_as_presence(true);
+    assert_eq!(
+        Column::deserialize(&mut de).unwrap(),
+        Column { required: true, unique: false, indexed: true, hidden: false }
+    );
+    assert!(from_str::<Column>(s).is_err());
+}
+
+#[test]
+fn end_of_document() {
+    let _ = simple_logger::init();
+
+    let item: Item = from_str(
+        r##"<item name="hello" source="world.rs"/> <!-- done --> <?render fast?>"##,
+    ).unwrap();
+    assert_eq!(item.name, "hello");
+
+    let second_root = r##"<item name="a" source="b"/><item name="c" source="d"/>"##;
+    assert!(from_str::<Item>(second_root).is_err());
+    assert!(from_str::<Vec<Item>>(second_root).is_err());
+
+    let trailing_text = r##"<item name="a" source="b"/> trailing"##;
+    assert!(from_str::<Item>(trailing_text).is_err());
+
+    let mut de = Deserializer::new_from_reader(second_root.as_bytes()).fragment(true);
+    let item = Item::deserialize(&mut de).unwrap();
+    assert_eq!(item.name, "a");
+    match de.end().unwrap_err().kind() {
+        ErrorKind::UnexpectedToken(_, found) => assert!(found.starts_with("StartElement")),
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn strict_root() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Order {
+        id: u32,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename = "inv:Invoice")]
+    struct Invoice {
+        id: u32,
+    }
+
+    let read = |s: &str| {
+        let mut de = Deserializer::new_from_reader(s.as_bytes()).strict_root(true);
+        Order::deserialize(&mut de)
+    };
+    assert_eq!(read(r#"<Order id="1"/>"#).unwrap(), Order { id: 1 });
+    assert_eq!(read(r#"<o:Order xmlns:o="urn:o" id="2"/>"#).unwrap(), Order { id: 2 });
+    match read(r#"<Invoice id="3"/>"#).unwrap_err().kind() {
+        ErrorKind::UnexpectedRoot(expected, found) => {
+            assert_eq!(expected, "Order");
+            assert_eq!(found, "Invoice");
+        },
+        e => panic!("unexpected error: {}", e),
+    }
+
+    let s = r#"<inv:Invoice xmlns:inv="urn:inv" id="4"/>"#;
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).strict_root(true);
+    assert_eq!(Invoice::deserialize(&mut de).unwrap(), Invoice { id: 4 });
+
+    let s = r#"<inv:Order xmlns:inv="urn:inv" id="5"/>"#;
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).strict_root(true);
+    let error = Invoice::deserialize(&mut de).unwrap_err();
+    assert_eq!(error.to_string(), "expected the root element inv:Invoice, found inv:Order");
+
+    assert_eq!(from_str::<Order>(r#"<Invoice id="6"/>"#).unwrap(), Order { id: 6 });
+}
+
+#[test]
+fn capture_comments() {
+    let _ = simple_logger::init();
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Section {
+        #[serde(rename = "$comments")]
+        comments: Vec<String>,
+        title: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Book {
+        #[serde(rename = "$comments")]
+        comments: Vec<String>,
+        #[serde(rename = "$processing_instructions")]
+        processing_instructions: Vec<String>,
+        title: String,
+        section: Section,
+    }
+
+    let s = r##"
+        <?xml version="1.0"?>
+        <!-- Copyright 2026 -->
+        <?app-version 3.2?>
+        <book>
+            <title>Guide<!-- draft --></title>
+            <?page-break?>
+            <section>
+                <!-- first -->
+                <title>Intro</title>
+            </section>
+            <!-- last -->
+        </book>
+    "##;
+
+    let mut de = Deserializer::new_from_reader(s.as_bytes()).capture_comments(true);
+    let book = Book::deserialize(&mut de).unwrap();
+    assert_eq!(book.comments, vec![" Copyright 2026 ", " last "]);
+    assert_eq!(book.processing_instructions, vec!["app-version 3.2", "page-break"]);
+    assert_eq!(book.title, "Guide");
+    assert_eq!(book.section.comments, vec![" first "]);
+
+    let book: Book = from_str(s).unwrap();
+    assert!(book.comments.is_empty() && book.processing_instructions.is_empty());
+
+    let element: Element = from_str("<doc><?render fast?><!-- note --></doc>").unwrap();
+    assert_eq!(
+        element.children,
+        vec![
+            Node::ProcessingInstruction {
+                target: "render".to_string(),
+                data: Some("fast".to_string()),
+            },
+            Node::Comment(" note ".to_string()),
+        ]
+    );
+    assert_eq!(element.to_string(), "<doc><?render fast?><!-- note --></doc>");
+}